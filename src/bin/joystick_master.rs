@@ -8,65 +8,57 @@
 #![no_std]
 #![no_main]
 
-use core::{cell::RefCell, fmt::Write};
+use core::cell::RefCell;
 
 use embassy_executor::Spawner;
-use embassy_time::{Duration, Timer};
+use embassy_time::{Duration, Instant, Ticker};
 use esp_alloc as _;
 use esp_backtrace as _;
 use esp_hal::{
     analog::adc::{Adc, AdcConfig, Attenuation},
-    clock::CpuClock,
-    rng::Rng,
-    timer::timg::TimerGroup,
+    gpio::{Input, InputConfig, Pull},
 };
 use esp_println::println;
-use esp_wifi::{EspWifiController, esp_now::PeerInfo, init};
-use heapless::String;
-use robo_remote::{self as _, Map, mk_static};
+use robo_remote::{
+    self as _, Map,
+    board::{self, Board, Role},
+    control::send_policy::SendPolicy,
+    error::Recovery,
+    protocol::message::Message,
+    transport::{esp_now::EspNowTransport, send_message},
+};
 
 const ADC_SHIFT: u16 = 2144; // to obtain zero at the minimum of a joystick range
 
+// the bridge until paired with one
 const PEER_ADDRESS: [u8; 6] = [0x54, 0x32, 0x04, 0x32, 0xf2, 0xb8];
+const ROLE: Role = Role::Remote { peer: PEER_ADDRESS };
 
-#[cfg(debug_assertions)]
-const INTERVAL: Duration = Duration::from_millis(500);
-
-// so MCU shouldn't halt
-#[cfg(not(debug_assertions))]
-const INTERVAL: Duration = Duration::from_nanos(10);
+// joystick is sampled and sent at this rate
+const SEND_RATE_HZ: u64 = 50;
+// percents, smaller changes are not worth the airtime
+const DEADBAND: f32 = 1.0;
+// well below the car's failsafe timeout
+const KEEPALIVE: Duration = Duration::from_millis(250);
 
 const WIFI_CHANNEL: u8 = 3;
 // TODO: master address
 #[esp_hal_embassy::main]
 async fn main(_spawner: Spawner) -> ! {
-    esp_println::logger::init_logger_from_env();
-    let config = esp_hal::Config::default().with_cpu_clock(CpuClock::max());
-    let peripherals = esp_hal::init(config);
-
-    esp_alloc::heap_allocator!(size: 72 * 1024);
-
-    let timg0 = TimerGroup::new(peripherals.TIMG0);
-
-    let esp_wifi_ctrl = &*mk_static!(
-        EspWifiController<'static>,
-        init(
-            timg0.timer0,
-            Rng::new(peripherals.RNG),
-            peripherals.RADIO_CLK,
-        )
-        .unwrap()
-    );
-
-    let wifi = peripherals.WIFI;
-    let mut esp_now = esp_wifi::esp_now::EspNow::new(esp_wifi_ctrl, wifi).unwrap();
-    println!("esp-now version {}", esp_now.version().unwrap());
-
-    use esp_hal::timer::systimer::SystemTimer;
-    let systimer = SystemTimer::new(peripherals.SYSTIMER);
-    esp_hal_embassy::init(systimer.alarm0);
-    esp_now.set_channel(WIFI_CHANNEL).unwrap();
-    let mut data: String<64> = String::new();
+    let Board {
+        peripherals,
+        esp_now,
+        address,
+        mut rng,
+        ..
+    } = board::init(WIFI_CHANNEL, ROLE);
+
+    // hold BOOT for a second right after start-up to pair with the bridge
+    let mut button = Input::new(peripherals.GPIO9, InputConfig::default().with_pull(Pull::Up));
+    let pair = board::pair_requested(&mut button).await;
+    let (mut transport, peer) =
+        board::bind_link(EspNowTransport::new(esp_now, address), ROLE, pair, &mut rng).await;
+    let peer = peer.first().copied().unwrap_or(PEER_ADDRESS);
 
     let analog_pin = peripherals.GPIO1;
     let mut adc1_config = AdcConfig::new();
@@ -81,18 +73,10 @@ async fn main(_spawner: Spawner) -> ! {
     let mut pin2 = adc12_config.enable_pin(analog_pin2, Attenuation::_11dB);
     let mut adc12 = Adc::new(adc.borrow_mut(), adc12_config);
 
+    let mut left = SendPolicy::new(DEADBAND, KEEPALIVE);
+    let mut right = SendPolicy::new(DEADBAND, KEEPALIVE);
+    let mut ticker = Ticker::every(Duration::from_hz(SEND_RATE_HZ));
     loop {
-        if !esp_now.peer_exists(&PEER_ADDRESS) {
-            esp_now
-                .add_peer(PeerInfo {
-                    peer_address: PEER_ADDRESS,
-                    lmk: None,
-                    channel: None,
-                    encrypt: false,
-                })
-                .unwrap();
-        }
-
         let x = adc1.read_oneshot(&mut pin).await.saturating_sub(ADC_SHIFT);
         println!("X value: {}", x);
 
@@ -111,14 +95,24 @@ async fn main(_spawner: Spawner) -> ! {
         println!("X normed: {}", x);
         println!("Y normed: {}", y);
 
-        data.clear();
-        writeln!(&mut data, "LSPEED:{};", x).unwrap(); // todo
-        let status = esp_now.send_async(&PEER_ADDRESS, data.as_bytes()).await;
-        println!("Send broadcast status: {:?}", status);
-        data.clear();
-        writeln!(&mut data, "RSPEED:{};", y).unwrap(); // todo
-        let status = esp_now.send_async(&PEER_ADDRESS, data.as_bytes()).await;
-        println!("Send broadcast status: {:?}", status);
-        Timer::after(INTERVAL).await;
+        let now = Instant::now();
+        // failed sends go again with the next sample instead of waiting for the keepalive
+        if left.should_send(x, now) {
+            if let Err(err) = send_message(&mut transport, &peer, &Message::LeftSpeed(x)).await {
+                println!("Sending left speed failed: {}", err);
+                if err.recovery() == Recovery::Retry {
+                    left.failed();
+                }
+            }
+        }
+        if right.should_send(y, now) {
+            if let Err(err) = send_message(&mut transport, &peer, &Message::RightSpeed(y)).await {
+                println!("Sending right speed failed: {}", err);
+                if err.recovery() == Recovery::Retry {
+                    right.failed();
+                }
+            }
+        }
+        ticker.next().await;
     }
 }
@@ -8,58 +8,39 @@
 #![no_std]
 #![no_main]
 
-use core::str::{self};
-
 use embassy_executor::Spawner;
-use embassy_time::{Duration, Timer};
+use embassy_futures::select::{Either, select};
 use esp_alloc as _;
 use esp_backtrace as _;
 use esp_hal::{
-    clock::CpuClock,
-    rng::Rng,
-    timer::timg::TimerGroup,
+    gpio::{Input, InputConfig, Pull},
     uart::{self, Uart},
 };
 use esp_println::println;
-use esp_wifi::{EspWifiController, esp_now::PeerInfo, init};
-use robo_remote::{self as _, mk_static};
+use robo_remote::{
+    self as _,
+    board::{self, Board, Role},
+    error::{Error, Recovery},
+    protocol::{parser::parse_all, stream::StreamParser},
+    transport::{MAX_FRAME, Transport, esp_now::EspNowTransport, send_message, uart::UartTransport},
+};
 
 const THE_ADDRESS: [u8; 6] = [0x54u8, 0x32, 0x04, 0x32, 0xf2, 0xb8];
 const WIFI_CHANNEL: u8 = 3;
-
-// so MCU shouldn't halt
-const INTERVAL: Duration = Duration::from_nanos(1);
+// whatever is on the other end of the wire
+const UART_PEER: [u8; 6] = [0; 6];
 
 // TODO: master address
 #[esp_hal_embassy::main]
 async fn main(_spawner: Spawner) -> ! {
-    esp_println::logger::init_logger_from_env();
-    let config = esp_hal::Config::default().with_cpu_clock(CpuClock::max());
-    let peripherals = esp_hal::init(config);
-
-    esp_alloc::heap_allocator!(size: 72 * 1024);
-
-    let timg0 = TimerGroup::new(peripherals.TIMG0);
-
-    let esp_wifi_ctrl = &*mk_static!(
-        EspWifiController<'static>,
-        init(
-            timg0.timer0,
-            Rng::new(peripherals.RNG),
-            peripherals.RADIO_CLK,
-        )
-        .unwrap()
-    );
-
-    let wifi = peripherals.WIFI;
-    let mut esp_now = esp_wifi::esp_now::EspNow::new(esp_wifi_ctrl, wifi).unwrap();
-    println!("esp-now version {}", esp_now.version().unwrap());
-    esp_now.set_channel(WIFI_CHANNEL).unwrap();
-    use esp_hal::timer::systimer::SystemTimer;
-    let systimer = SystemTimer::new(peripherals.SYSTIMER);
-    esp_hal_embassy::init(systimer.alarm0);
+    let Board {
+        peripherals,
+        esp_now,
+        mut rng,
+        ..
+    } = board::init(WIFI_CHANNEL, Role::Bridge);
 
-    let mut uart1 = Uart::new(
+    let uart1 = Uart::new(
         peripherals.UART1,
         uart::Config::default().with_baudrate(115200),
     )
@@ -68,24 +49,71 @@ async fn main(_spawner: Spawner) -> ! {
     .with_tx(peripherals.GPIO2)
     .into_async();
 
+    // hold BOOT for a second right after start-up to pair with the joystick
+    let mut button = Input::new(peripherals.GPIO9, InputConfig::default().with_pull(Pull::Up));
+    let pair = board::pair_requested(&mut button).await;
+    let (mut radio, bindings) = board::bind_link(
+        EspNowTransport::new(esp_now, THE_ADDRESS),
+        Role::Bridge,
+        pair,
+        &mut rng,
+    )
+    .await;
+    let mut wire = UartTransport::new(uart1, THE_ADDRESS, UART_PEER);
+    // the wire side streams messages back, they go to the joystick
+    let mut stream: StreamParser<MAX_FRAME> = StreamParser::new();
+    let mut buf = [0u8; 32];
+
     loop {
-        let r = esp_now.receive_async().await;
-        if r.info.dst_address == THE_ADDRESS {
-            let data = r.data();
-            let rec = str::from_utf8(data).unwrap_or("Data is not received properly");
-            uart1.write_async(data).await.unwrap();
-            println!("Received {}", rec);
-            if !esp_now.peer_exists(&r.info.src_address) {
-                esp_now
-                    .add_peer(PeerInfo {
-                        peer_address: r.info.src_address,
-                        lmk: None,
-                        channel: None,
-                        encrypt: false,
-                    })
-                    .unwrap();
+        let frame = match select(radio.receive(), wire.read(&mut buf)).await {
+            Either::First(Ok(frame)) => frame,
+            Either::First(Err(err)) => {
+                println!("Receiving error {:?}", err);
+                continue;
+            }
+            Either::Second(Ok(len)) => {
+                for &byte in &buf[..len] {
+                    match stream.push(byte) {
+                        Some(Ok(message)) => {
+                            // nobody to send it back to until paired
+                            let Some(joystick) = bindings.first() else {
+                                println!("{:?} from the wire dropped, not paired", message);
+                                continue;
+                            };
+                            if let Err(err) = send_message(&mut radio, joystick, &message).await {
+                                println!("Sending {:?} back failed: {}", message, err);
+                            }
+                        }
+                        Some(Err(err)) => println!("Bad message on the wire: {}", err),
+                        None => (),
+                    }
+                }
+                continue;
+            }
+            Either::Second(Err(err)) => {
+                println!("Wire error {:?}", err);
+                continue;
+            }
+        };
+        for message in parse_all(&frame.data) {
+            match message {
+                Ok(message) => println!("Received {:?}", message),
+                Err(err) => println!("Received a bad message: {}", err),
+            }
+        }
+        // a frame that didn't make it over the wire gets one more go, the next one
+        // replaces it anyway
+        for _ in 0..2 {
+            match wire.send(&UART_PEER, &frame.data).await.map_err(Error::from) {
+                Ok(()) => break,
+                Err(err) if err.recovery() == Recovery::Retry => {
+                    println!("Forwarding error {}, retrying", err)
+                }
+                Err(err) => {
+                    println!("Forwarding error {}", err);
+                    break;
+                }
             }
         }
-        Timer::after(INTERVAL).await;
     }
 }
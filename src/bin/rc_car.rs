@@ -8,66 +8,591 @@
 #![no_std]
 #![no_main]
 
-use core::str::{self};
+use core::{
+    cell::{Cell, RefCell},
+    sync::atomic::{AtomicU8, AtomicU32, Ordering},
+};
 
+use bt_hci::controller::ExternalController;
 use embassy_executor::Spawner;
-use embassy_futures::select::{Either, select};
-use embassy_time::{Duration, Timer};
+use embassy_futures::select::{Either, Either3, select, select3};
+use embassy_sync::{
+    blocking_mutex::{Mutex, raw::CriticalSectionRawMutex},
+    channel::Channel,
+    signal::Signal,
+};
+use embassy_time::{Duration, Instant, Ticker, Timer, with_deadline};
 use esp_alloc as _;
 use esp_backtrace as _;
 use esp_hal::{
-    clock::CpuClock,
+    analog::adc::{Adc, AdcCalCurve, AdcConfig, Attenuation},
+    gpio::{Input, InputConfig, Level, Output, OutputConfig, Pull},
     mcpwm::{McPwm, PeripheralClockConfig, operator::PwmPinConfig, timer::PwmWorkingMode},
-    rng::Rng,
+    peripherals::{ADC1, TIMG1},
     time::Rate,
-    timer::timg::TimerGroup,
+    timer::timg::{MwdtStage, MwdtStageAction, TimerGroup, Wdt},
 };
 use esp_println::println;
-use esp_wifi::{EspWifiController, esp_now::PeerInfo, init};
+use esp_storage::FlashStorage;
+use esp_wifi::ble::controller::BleConnector;
+#[cfg(feature = "web")]
+use esp_wifi::wifi::{WifiController, WifiDevice};
+use heapless::{String, Vec};
+#[cfg(not(feature = "web"))]
+use robo_remote::{
+    board::{Board, Role},
+    transport::{esp_now::EspNowTransport, signed::SignedTransport},
+};
+#[cfg(feature = "web")]
+use robo_remote::{
+    board::AccessPointBoard,
+    web::{self, WEB_PEER},
+};
 use robo_remote::{
     self as _,
-    drivers::motor::Motor,
-    mk_static,
-    protocol::{message, parser::parse},
+    ble::{self, BLE_PEER},
+    board, crash, estop,
+    control::{
+        arbiter::{Arbiter, DriveCommand},
+        arming::ArmingGate,
+        obstacle::ObstacleLimiter,
+        owner::Ownership,
+        supervisor::{Heartbeat, Supervisor},
+    },
+    drivers::{
+        battery::{BatteryConfig, BatteryMonitor, BatteryState, Chemistry},
+        motor::Motor,
+        ultrasonic::Ultrasonic,
+    },
+    error::{Error, Recovery},
+    params::{self, MAX_PARAMS, ParamDef, ParamTable},
+    protocol::{
+        comands::CRASH_PREFIX,
+        hello::{Hello, Vehicle},
+        message::{CRASH_LEN, Message, SafetyState},
+        param::{ParamFlags, ParamType, ParamValue},
+    },
+    transport::{
+        Frame, Inbox, LinkMetrics, PeerId, Transport,
+        loopback::{LoopbackLink, LoopbackTransport},
+        send_message,
+    },
 };
 
 const THE_ADDRESS: [u8; 6] = [0x54u8, 0x32, 0x04, 0x32, 0xf2, 0xb8];
 const WIFI_CHANNEL: u8 = 3;
+#[cfg(feature = "web")]
+const SSID: &str = "robo_remote";
+// WPA2 passphrase of the soft-AP, set at build time
+#[cfg(feature = "web")]
+const PASSWORD: &str = env!("ROBO_AP_PASSWORD");
+#[cfg(feature = "web")]
+const _: () = assert!(PASSWORD.len() >= web::MIN_PASSWORD_LEN, "ROBO_AP_PASSWORD is too short");
 
-// so MCU shouldn't halt
-const INTERVAL: Duration = Duration::from_nanos(10);
+// sticks must rest centred this long after boot or a failsafe before the car moves
+const ARMING_HOLD: Duration = Duration::from_millis(500);
 
-// Is it enough time to reconnect/react?
-const TIMEOUT: Duration = Duration::from_secs(1);
+// motors are updated at 50 Hz no matter how often packets arrive
+const CONTROL_PERIOD: Duration = Duration::from_millis(20);
 
-// TODO: master address
-#[esp_hal_embassy::main]
-async fn main(_spawner: Spawner) -> ! {
-    esp_println::logger::init_logger_from_env();
-    let config = esp_hal::Config::default().with_cpu_clock(CpuClock::max());
-    let peripherals = esp_hal::init(config);
+const TELEMETRY_INTERVAL: Duration = Duration::from_secs(2);
+
+// an idle link still checks in this often
+const LINK_BEAT: Duration = Duration::from_millis(500);
+// flash writes stall the executor, they are put off and done together
+const SAVE_DELAY: Duration = Duration::from_secs(1);
+// longest the executor stalls for one flash write, a 4 KiB sector erase at the
+// worst the flash datasheets allow
+const FLASH_STALL_MS: u64 = 400;
+const SUPERVISOR_PERIOD: Duration = Duration::from_millis(100);
+// from the first missed deadline to the reset, a flash write delays the feed
+const WATCHDOG_TIMEOUT_MS: u64 = FLASH_STALL_MS + 500;
+
+// 2S Li-ion pack through a 100k/33k divider, sampled every control period
+const BATTERY: BatteryConfig = BatteryConfig {
+    chemistry: Chemistry::LiIon,
+    cells: 2,
+    divider_ratio: (100.0 + 33.0) / 33.0,
+    filter: 0.02,
+    hysteresis: 100,
+    low_power_limit: 50.0,
+};
+
+// centimetres: full stop, start of slowing down, release band; then how long missed
+// echoes keep the last limit, several readings
+const OBSTACLE: ObstacleLimiter =
+    ObstacleLimiter::new(20.0, 60.0, 10.0, Duration::from_millis(500));
+const RANGING_INTERVAL: Duration = Duration::from_millis(60);
+
+// radio -> arbiter
+static COMMANDS: Channel<CriticalSectionRawMutex, ([u8; 6], Message), 8> = Channel::new();
+// arbiter -> motor control
+static TARGET: Signal<CriticalSectionRawMutex, DriveCommand> = Signal::new();
+// radio -> storage, the remote's latest boot epoch
+static EPOCH: Signal<CriticalSectionRawMutex, (PeerId, u32)> = Signal::new();
+// links -> telemetry, the controller the crash report went out to
+static CRASH_SENT: Signal<CriticalSectionRawMutex, PeerId> = Signal::new();
+// links -> storage, a persistent parameter changed
+static SAVE_PARAMS: Signal<CriticalSectionRawMutex, ()> = Signal::new();
+// telemetry -> radio
+static OUTGOING: Channel<CriticalSectionRawMutex, Frame, 4> = Channel::new();
+// telemetry -> ble
+static BLE_OUTGOING: Channel<CriticalSectionRawMutex, Frame, 4> = Channel::new();
+// GATT server <-> car
+static BLE_LINK: LoopbackLink = LoopbackLink::new();
+// telemetry -> browser
+#[cfg(feature = "web")]
+static WEB_OUTGOING: Channel<CriticalSectionRawMutex, Frame, 4> = Channel::new();
+// web server <-> car
+#[cfg(feature = "web")]
+static WEB_LINK: LoopbackLink = LoopbackLink::new();
+
+// telemetry state
+static CONTROLLER: Mutex<CriticalSectionRawMutex, Cell<Option<[u8; 6]>>> =
+    Mutex::new(Cell::new(None));
+static OWNER: Mutex<CriticalSectionRawMutex, Cell<Option<[u8; 6]>>> = Mutex::new(Cell::new(None));
+static SAFETY: Mutex<CriticalSectionRawMutex, Cell<SafetyState>> =
+    Mutex::new(Cell::new(SafetyState::Disarmed));
+static BATTERY_CHARGE: AtomicU8 = AtomicU8::new(0);
+// of each link, as last seen by its task
+static RADIO_METRICS: Mutex<CriticalSectionRawMutex, Cell<LinkMetrics>> =
+    Mutex::new(Cell::new(LinkMetrics::new()));
+static BLE_METRICS: Mutex<CriticalSectionRawMutex, Cell<LinkMetrics>> =
+    Mutex::new(Cell::new(LinkMetrics::new()));
+#[cfg(feature = "web")]
+static WEB_METRICS: Mutex<CriticalSectionRawMutex, Cell<LinkMetrics>> =
+    Mutex::new(Cell::new(LinkMetrics::new()));
+
+// checked by the supervisor, which feeds the watchdog while all are on time
+static CONTROL_ALIVE: Heartbeat =
+    Heartbeat::new("control", Duration::from_millis(FLASH_STALL_MS + 200));
+static ARBITER_ALIVE: Heartbeat = Heartbeat::new("arbiter", Duration::from_secs(2));
+#[cfg(not(feature = "web"))]
+static RADIO_ALIVE: Heartbeat = Heartbeat::new("radio", Duration::from_secs(2));
+static BLE_ALIVE: Heartbeat = Heartbeat::new("ble", Duration::from_secs(2));
+#[cfg(feature = "web")]
+static WEB_ALIVE: Heartbeat = Heartbeat::new("web", Duration::from_secs(2));
+
+// tunables, the ids are what PGET and PSET take
+const PARAM_TIMEOUT: u8 = 0;
+const PARAM_LEFT_GAIN: u8 = 1;
+const PARAM_RIGHT_GAIN: u8 = 2;
+const PARAM_EXPO: u8 = 3;
+static PARAM_DEFS: [ParamDef; 4] = [
+    // Is it enough time to reconnect/react?
+    ParamDef {
+        id: PARAM_TIMEOUT,
+        name: "timeout_ms",
+        kind: ParamType::Int,
+        min: 100.0,
+        max: 5000.0,
+        default: ParamValue::Int(1000),
+        flags: ParamFlags::PERSIST,
+    },
+    // percents of the command a motor gets, evens out a car that pulls to one side
+    ParamDef {
+        id: PARAM_LEFT_GAIN,
+        name: "left_gain",
+        kind: ParamType::Float,
+        min: 50.0,
+        max: 100.0,
+        default: ParamValue::Float(100.0),
+        flags: ParamFlags::PERSIST,
+    },
+    ParamDef {
+        id: PARAM_RIGHT_GAIN,
+        name: "right_gain",
+        kind: ParamType::Float,
+        min: 50.0,
+        max: 100.0,
+        default: ParamValue::Float(100.0),
+        flags: ParamFlags::PERSIST,
+    },
+    // 0 is linear, 1 cubic: finer control around the centre
+    ParamDef {
+        id: PARAM_EXPO,
+        name: "expo",
+        kind: ParamType::Float,
+        min: 0.0,
+        max: 1.0,
+        default: ParamValue::Float(0.0),
+        flags: ParamFlags::PERSIST,
+    },
+];
+static PARAMS: Mutex<CriticalSectionRawMutex, RefCell<ParamTable>> =
+    Mutex::new(RefCell::new(ParamTable::new(&PARAM_DEFS)));
+
+// f32 bits of the last distance in centimetres
+const NO_ECHO: u32 = u32::MAX;
+static DISTANCE: AtomicU32 = AtomicU32::new(NO_ECHO);
 
-    esp_alloc::heap_allocator!(size: 72 * 1024);
+#[embassy_executor::task]
+async fn rangefinder(mut sensor: Ultrasonic<Output<'static>, Input<'static>>) {
+    loop {
+        let distance = sensor.measure().await.map_or(NO_ECHO, f32::to_bits);
+        DISTANCE.store(distance, Ordering::Relaxed);
+        Timer::after(RANGING_INTERVAL).await;
+    }
+}
+
+// the expo curve, then the motor's gain, both in percents
+fn shape(speed: f32, expo: f32, gain: f32) -> f32 {
+    let curved = speed * (1.0 - expo) + expo * speed * speed * speed / 10_000.0;
+    curved * gain / 100.0
+}
 
-    let timg0 = TimerGroup::new(peripherals.TIMG0);
+// controller silence before the failsafe
+fn timeout() -> Duration {
+    Duration::from_millis(PARAMS.lock(|table| table.borrow().int(PARAM_TIMEOUT)) as u64)
+}
+
+fn distance() -> Option<f32> {
+    match DISTANCE.load(Ordering::Relaxed) {
+        NO_ECHO => None,
+        bits => Some(f32::from_bits(bits)),
+    }
+}
 
-    let esp_wifi_ctrl = &*mk_static!(
-        EspWifiController<'static>,
-        init(
-            timg0.timer0,
-            Rng::new(peripherals.RNG),
-            peripherals.RADIO_CLK,
+// commands from the link go to the arbiter, outgoing frames to the link
+async fn serve_link<T: Transport>(
+    transport: &mut T,
+    outgoing: &Channel<CriticalSectionRawMutex, Frame, 4>,
+    metrics: &Mutex<CriticalSectionRawMutex, Cell<LinkMetrics>>,
+    alive: &Heartbeat,
+) -> ! {
+    let mut inbox = Inbox::new();
+    loop {
+        alive.beat();
+        match select3(
+            inbox.receive(transport),
+            outgoing.receive(),
+            Timer::after(LINK_BEAT),
         )
-        .unwrap()
+        .await
+        {
+            // answered here, the round trip shouldn't include the arbiter
+            Either3::First(Ok((src, Message::Ping(value)))) => {
+                // a lost pong is a lost measurement, nothing to retry
+                if let Err(err) = send_message(transport, &src, &Message::Pong(value)).await {
+                    println!("Sending pong failed: {}", err);
+                }
+            }
+            // the remote decides whether it can drive us
+            Either3::First(Ok((src, Message::Hello(hello)))) => {
+                println!("Hello from {:02x?}: {:?}", src, hello);
+                let answer = Message::Hello(Hello::local(Vehicle::Car));
+                if let Err(err) = send_message(transport, &src, &answer).await {
+                    println!("Sending hello failed: {}", err);
+                }
+            }
+            Either3::First(Ok((
+                src,
+                message @ (Message::ParamGet(_) | Message::ParamSet(..) | Message::ParamList),
+            ))) => serve_params(transport, &src, &message).await,
+            Either3::First(Ok((src, message))) => {
+                println!("Received {:?}", message);
+                COMMANDS.send((src, message)).await;
+            }
+            // the next frame is waited for anyway, the arbiter fails safe if none comes
+            Either3::First(Err(err)) => println!("Receiving error {:?}", err),
+            Either3::Second(frame) => {
+                let status = transport.send(&frame.peer, &frame.data).await;
+                println!("Send telemetry status: {:?}", status);
+                if status.is_ok() && frame.data.starts_with(CRASH_PREFIX.as_bytes()) {
+                    CRASH_SENT.signal(frame.peer);
+                }
+            }
+            Either3::Third(()) => (),
+        }
+        metrics.lock(|metrics| metrics.set(transport.metrics()));
+    }
+}
+
+// PGET, PSET and PLIST are answered with a PARAM for each parameter they name
+async fn serve_params<T: Transport>(transport: &mut T, src: &PeerId, message: &Message) {
+    let ids: Vec<u8, MAX_PARAMS> = match *message {
+        Message::ParamSet(id, value) => {
+            // only whoever drives the car tunes it, the bound remote once paired
+            if OWNER.lock(|owner| owner.get()) != Some(*src) {
+                println!("Parameter {} from {:02x?} ignored, not the owner", id, src);
+            } else {
+                set_param(id, value);
+            }
+            Vec::from_iter([id])
+        }
+        Message::ParamGet(id) => Vec::from_iter([id]),
+        _ => PARAMS.lock(|table| table.borrow().ids().collect()),
+    };
+    for id in ids {
+        let Some(info) = PARAMS.lock(|table| table.borrow().info(id)) else {
+            println!("No parameter {}", id);
+            continue;
+        };
+        if let Err(err) = send_message(transport, src, &Message::Param(info)).await {
+            println!("Sending parameter {} failed: {}", id, err);
+        }
+    }
+}
+
+fn set_param(id: u8, value: ParamValue) {
+    let set = PARAMS.lock(|table| table.borrow_mut().set(id, value));
+    match set {
+        Ok(value) => println!("Parameter {} = {}", id, value),
+        Err(err) => {
+            println!("Parameter {} not set: {:?}", id, err);
+            return;
+        }
+    }
+    if PARAMS.lock(|table| table.borrow().persists(id)) {
+        SAVE_PARAMS.signal(());
+    }
+}
+
+#[embassy_executor::task]
+async fn storage() {
+    loop {
+        let first = select(EPOCH.wait(), SAVE_PARAMS.wait()).await;
+        Timer::after(SAVE_DELAY).await;
+        // whatever was asked for meanwhile goes along, only the latest epoch counts
+        let (mut epoch, mut params) = match first {
+            Either::First(epoch) => (Some(epoch), false),
+            Either::Second(()) => (None, true),
+        };
+        epoch = EPOCH.try_take().or(epoch);
+        params |= SAVE_PARAMS.try_take().is_some();
+
+        if let Some((peer, epoch)) = epoch {
+            if let Err(err) = board::save_epoch(peer, epoch) {
+                println!("Epoch of {:02x?} is not saved: {}", peer, err);
+            }
+            // the control loop and the supervisor catch up between the two stalls
+            Timer::after(SUPERVISOR_PERIOD).await;
+        }
+        if params {
+            let table = PARAMS.lock(|table| table.borrow().clone());
+            let saved = params::save(&mut FlashStorage::new(), &table).map_err(|_| Error::Config);
+            if let Err(err) = saved {
+                println!("Saving parameters failed: {}", err);
+            }
+        }
+    }
+}
+
+#[cfg(not(feature = "web"))]
+#[embassy_executor::task]
+async fn radio(mut transport: SignedTransport<EspNowTransport<'static>>) {
+    serve_link(&mut transport, &OUTGOING, &RADIO_METRICS, &RADIO_ALIVE).await
+}
+
+#[embassy_executor::task]
+async fn ble_link(mut transport: LoopbackTransport<'static>) {
+    serve_link(&mut transport, &BLE_OUTGOING, &BLE_METRICS, &BLE_ALIVE).await
+}
+
+#[embassy_executor::task]
+async fn ble_host(
+    controller: ExternalController<BleConnector<'static>, 20>,
+    link: LoopbackTransport<'static>,
+) {
+    ble::run(controller, THE_ADDRESS, link).await
+}
+
+#[cfg(feature = "web")]
+#[embassy_executor::task]
+async fn wifi_ap(controller: WifiController<'static>) {
+    web::run_access_point(controller, SSID, PASSWORD, WIFI_CHANNEL).await
+}
+
+#[cfg(feature = "web")]
+#[embassy_executor::task]
+async fn net_task(mut runner: embassy_net::Runner<'static, WifiDevice<'static>>) {
+    runner.run().await
+}
+
+#[cfg(feature = "web")]
+#[embassy_executor::task]
+async fn dhcp(stack: embassy_net::Stack<'static>) {
+    web::run_dhcp(stack).await
+}
+
+#[cfg(feature = "web")]
+#[embassy_executor::task]
+async fn web_server(stack: embassy_net::Stack<'static>, link: LoopbackTransport<'static>) {
+    web::run_server(stack, link).await
+}
+
+#[cfg(feature = "web")]
+#[embassy_executor::task]
+async fn web_link(mut transport: LoopbackTransport<'static>) {
+    serve_link(&mut transport, &WEB_OUTGOING, &WEB_METRICS, &WEB_ALIVE).await
+}
+
+#[embassy_executor::task]
+async fn arbiter(bound: Option<[u8; 6]>) {
+    // a reset doesn't release an emergency stop
+    let mut arbiter = if estop::is_latched() {
+        println!("Emergency stop still latched");
+        SAFETY.lock(|safety| safety.set(SafetyState::EmergencyStopped));
+        Arbiter::latched()
+    } else {
+        Arbiter::default()
+    };
+    let mut gate = ArmingGate::new(ARMING_HOLD);
+    let mut ownership = Ownership::new(bound);
+    OWNER.lock(|owner| owner.set(ownership.owner()));
+    let mut deadline = Instant::now() + timeout();
+    loop {
+        ARBITER_ALIVE.beat();
+        // the timeout may be longer than the heartbeat allows
+        let wake = deadline.min(Instant::now() + LINK_BEAT);
+        let command = match with_deadline(wake, COMMANDS.receive()).await {
+            // whoever reaches the car may stop it
+            Ok((src, Message::EmergencyStop))
+                if ownership.owner().is_some_and(|owner| owner != src) =>
+            {
+                println!("Emergency stop from {:02x?}", src);
+                arbiter.handle(&Message::EmergencyStop)
+            }
+            Ok((src, message)) => {
+                // someone else's commands don't keep the link alive either
+                if !ownership.accept(&src) {
+                    continue;
+                }
+                deadline = Instant::now() + timeout();
+                CONTROLLER.lock(|controller| controller.set(Some(src)));
+                if message == Message::Release {
+                    ownership.release();
+                }
+                gate.update(arbiter.handle(&message), Instant::now())
+            }
+            // only checking in
+            Err(_) if Instant::now() < deadline => continue,
+            Err(_) => {
+                println!("Disconnected");
+                deadline = Instant::now() + timeout();
+                ownership.release();
+                gate.disarm();
+                arbiter.failsafe()
+            }
+        };
+        OWNER.lock(|owner| owner.set(ownership.owner()));
+        estop::set_latched(arbiter.safety() == SafetyState::EmergencyStopped);
+        let state = match arbiter.safety() {
+            SafetyState::Armed if !gate.is_armed() => SafetyState::Disarmed,
+            state => state,
+        };
+        SAFETY.lock(|safety| safety.set(state));
+        TARGET.signal(command);
+    }
+}
+
+#[embassy_executor::task]
+async fn telemetry(crash: Option<String<CRASH_LEN>>) {
+    let mut ticker = Ticker::every(TELEMETRY_INTERVAL);
+    let mut reported = LinkMetrics::default();
+    // controllers are told about a crash once each
+    let mut told = None;
+    loop {
+        ticker.next().await;
+        let Some(controller) = CONTROLLER.lock(|controller| controller.get()) else {
+            continue;
+        };
+
+        let link = match controller {
+            BLE_PEER => &BLE_METRICS,
+            #[cfg(feature = "web")]
+            WEB_PEER => &WEB_METRICS,
+            _ => &RADIO_METRICS,
+        };
+        let metrics = link.lock(|metrics| metrics.get());
+        let loss = metrics.loss_since(&reported);
+        reported = metrics;
+
+        let battery = Message::Battery(BATTERY_CHARGE.load(Ordering::Relaxed));
+        let owner = Message::Owner(OWNER.lock(|owner| owner.get()));
+        let safety = Message::Safety(SAFETY.lock(|safety| safety.get()));
+        // sent again each tick until a link reports it went out
+        if let Some(sent_to) = CRASH_SENT.try_take() {
+            told = Some(sent_to);
+        }
+        let crash = crash
+            .as_ref()
+            .filter(|_| told != Some(controller))
+            .map(|record| Message::Crash(record.clone()));
+        let optional = [metrics.rssi.map(Message::Rssi), loss.map(Message::Loss), crash];
+        for message in [battery, owner, safety].into_iter().chain(optional.into_iter().flatten()) {
+            let frame = match Frame::from_message(controller, &message) {
+                Ok(frame) => frame,
+                Err(err) => {
+                    println!("Telemetry {:?} dropped: {}", message, err);
+                    continue;
+                }
+            };
+            match controller {
+                BLE_PEER => BLE_OUTGOING.send(frame).await,
+                #[cfg(feature = "web")]
+                WEB_PEER => WEB_OUTGOING.send(frame).await,
+                _ => OUTGOING.send(frame).await,
+            }
+        }
+    }
+}
+
+// Feeds the watchdog while every task checks in on time. A hung task gets the motors
+// stopped here, then the watchdog resets the car.
+#[embassy_executor::task]
+async fn supervisor(mut watchdog: Wdt<TIMG1>) {
+    #[cfg(not(feature = "web"))]
+    let tasks = [&CONTROL_ALIVE, &ARBITER_ALIVE, &RADIO_ALIVE, &BLE_ALIVE];
+    #[cfg(feature = "web")]
+    let tasks = [&CONTROL_ALIVE, &ARBITER_ALIVE, &WEB_ALIVE, &BLE_ALIVE];
+    let supervisor = Supervisor::new(&tasks, Instant::now());
+
+    watchdog.set_timeout(
+        MwdtStage::Stage0,
+        esp_hal::time::Duration::from_millis(WATCHDOG_TIMEOUT_MS),
     );
+    watchdog.set_stage_action(MwdtStage::Stage0, MwdtStageAction::ResetSystem);
+    watchdog.enable();
 
-    let wifi = peripherals.WIFI;
-    let mut esp_now = esp_wifi::esp_now::EspNow::new(esp_wifi_ctrl, wifi).unwrap();
-    println!("esp-now version {}", esp_now.version().unwrap());
-    esp_now.set_channel(WIFI_CHANNEL).unwrap();
-    use esp_hal::timer::systimer::SystemTimer;
-    let systimer = SystemTimer::new(peripherals.SYSTIMER);
-    esp_hal_embassy::init(systimer.alarm0);
+    let mut ticker = Ticker::every(SUPERVISOR_PERIOD);
+    loop {
+        ticker.next().await;
+        if let Err(task) = supervisor.check(Instant::now()) {
+            println!("{} missed its deadline", task.name());
+            crash::stop_motors();
+            crash::record(format_args!("watchdog: {} missed its deadline", task.name()));
+            core::future::pending::<()>().await;
+        }
+        watchdog.feed();
+    }
+}
+
+// TODO: master address
+#[esp_hal_embassy::main]
+async fn main(spawner: Spawner) -> ! {
+    let last_crash = crash::take();
+    if let Some(record) = &last_crash {
+        println!("Restarted after a crash: {}", record);
+    }
+    PARAMS.lock(|table| params::load(&mut FlashStorage::new(), &mut table.borrow_mut()));
+
+    #[cfg(not(feature = "web"))]
+    let Board {
+        peripherals,
+        wifi_controller,
+        esp_now,
+        mut rng,
+        ..
+    } = board::init(WIFI_CHANNEL, Role::Car);
+    #[cfg(feature = "web")]
+    let AccessPointBoard {
+        peripherals,
+        wifi_controller,
+        controller,
+        stack,
+        runner,
+    } = board::init_access_point(web::GATEWAY);
 
     // initialize peripheral
     let clock_cfg = PeripheralClockConfig::with_frequency(Rate::from_mhz(32)).unwrap();
@@ -77,7 +602,13 @@ async fn main(_spawner: Spawner) -> ! {
     mcpwm.operator0.set_timer(&mcpwm.timer0);
     mcpwm.operator1.set_timer(&mcpwm.timer1);
 
- 
+
+    // pulled low if the firmware panics
+    crash::register_motor_pin(&peripherals.GPIO2);
+    crash::register_motor_pin(&peripherals.GPIO3);
+    crash::register_motor_pin(&peripherals.GPIO4);
+    crash::register_motor_pin(&peripherals.GPIO5);
+
     let pwm_pins = mcpwm.operator0.with_pins(
         peripherals.GPIO2,
         PwmPinConfig::UP_ACTIVE_HIGH,
@@ -85,7 +616,7 @@ async fn main(_spawner: Spawner) -> ! {
         PwmPinConfig::UP_ACTIVE_HIGH,
     );
     let mut left_motor = Motor::new(pwm_pins.0, pwm_pins.1);
-    
+
 
     let pwm_pins2 = mcpwm.operator1.with_pins(
         peripherals.GPIO4,
@@ -103,74 +634,88 @@ async fn main(_spawner: Spawner) -> ! {
     mcpwm.timer0.start(timer_clock_cfg);
     mcpwm.timer1.start(timer_clock_cfg);
 
-   
-
-    // TODO: timeout
-    let mut receive_data = async move || {
-        let rec = esp_now.receive_async().await;
-        let received = if rec.info.dst_address == THE_ADDRESS {
-            let data = rec.data();
-            let received = str::from_utf8(data);
-
-           // println!("Received {:?}", rec);
-            if !esp_now.peer_exists(&rec.info.src_address) {
-                esp_now
-                    .add_peer(PeerInfo {
-                        peer_address: rec.info.src_address,
-                        lmk: None,
-                        channel: None,
-                        encrypt: false,
-                    })
-                    .unwrap();
-            }
+    let mut adc_config = AdcConfig::new();
+    let mut battery_pin = adc_config
+        .enable_pin_with_cal::<_, AdcCalCurve<ADC1>>(peripherals.GPIO0, Attenuation::_11dB);
+    let mut adc = Adc::new(peripherals.ADC1, adc_config).into_async();
+    let mut battery = BatteryMonitor::new(BATTERY);
 
-            Some(received.ok())
-        } else {
-            println!("Receiving error");
-            None
-        };
+    let sensor = Ultrasonic::new(
+        Output::new(peripherals.GPIO6, Level::Low, OutputConfig::default()),
+        Input::new(peripherals.GPIO7, InputConfig::default().with_pull(Pull::None)),
+    );
+    let mut obstacle = OBSTACLE;
 
-        return if let Some(received) = received.flatten() {
-            match parse(received) {
-                Ok(message) => {
-                    println!("Received {:?}", message);
-                    Some(message)
-                },
-                Err(err) => {
-                    println!("{}", err);
-                    None
-                }
-            }
-        } else {
-            None
-        };
+    spawner.spawn(rangefinder(sensor)).unwrap();
+    #[cfg(not(feature = "web"))]
+    let bound = {
+        // hold BOOT for a second right after start-up to pair with a remote
+        let mut button = Input::new(peripherals.GPIO9, InputConfig::default().with_pull(Pull::Up));
+        let pair = board::pair_requested(&mut button).await;
+        let transport = EspNowTransport::new(esp_now, THE_ADDRESS);
+        let (mut transport, bound) = board::bind_link(transport, Role::Car, pair, &mut rng).await;
+        // stored by the storage task instead of stalling the link
+        transport.on_new_epoch(|peer, epoch| EPOCH.signal((peer, epoch)));
+        spawner.spawn(radio(transport)).unwrap();
+        bound.first().copied()
     };
+    #[cfg(feature = "web")]
+    let bound = {
+        let (web_side, car_side) = WEB_LINK.endpoints(WEB_PEER, THE_ADDRESS);
+        spawner.spawn(wifi_ap(controller)).unwrap();
+        spawner.spawn(net_task(runner)).unwrap();
+        spawner.spawn(dhcp(stack)).unwrap();
+        spawner.spawn(web_server(stack, web_side)).unwrap();
+        spawner.spawn(web_link(car_side)).unwrap();
+        None
+    };
+    spawner.spawn(storage()).unwrap();
+    spawner.spawn(arbiter(bound)).unwrap();
+    spawner.spawn(telemetry(last_crash)).unwrap();
+    let (ble_side, car_side) = BLE_LINK.endpoints(BLE_PEER, THE_ADDRESS);
+    let connector = BleConnector::new(wifi_controller, peripherals.BT);
+    spawner.spawn(ble_host(ExternalController::new(connector), ble_side)).unwrap();
+    spawner.spawn(ble_link(car_side)).unwrap();
+    spawner.spawn(supervisor(TimerGroup::new(peripherals.TIMG1).wdt)).unwrap();
 
+    // motor control
+    let mut command = DriveCommand::STOP;
+    let mut ticker = Ticker::every(CONTROL_PERIOD);
     loop {
-        let res = select(receive_data(), Timer::after(TIMEOUT).into_future()).await;
+        CONTROL_ALIVE.beat();
+        if let Some(target) = TARGET.try_take() {
+            command = target;
+        }
 
-        let received = match res {
-            Either::First(rec) => rec,
-            Either::Second(_) => {
-                println!("Disconnected");
-                left_motor.stop();
-                right_motor.stop();
-                None
-            }
-        };
+        let battery_state = battery.update(adc.read_oneshot(&mut battery_pin).await);
+        BATTERY_CHARGE.store(battery.state_of_charge(), Ordering::Relaxed);
+        let power_limit = battery.power_limit();
+        obstacle.update(distance(), Instant::now());
+        let (left_gain, right_gain, expo) = PARAMS.lock(|table| {
+            let table = table.borrow();
+            let value = |id| table.float(id);
+            (value(PARAM_LEFT_GAIN), value(PARAM_RIGHT_GAIN), value(PARAM_EXPO))
+        });
 
-        if let Some(message) = received {
-            match message {
-                // Todo: make speed stable
-                message::Message::LeftSpeed(speed) => left_motor.run(speed.clamp(-100.0, 100.0) as i16),
-                message::Message::RightSpeed(speed) => right_motor.run(speed.clamp(-100.0, 100.0) as i16),
-                message::Message::Stop => {
-                    left_motor.stop();
-                    right_motor.stop();
-                }
+        let driven = if battery_state == BatteryState::Cutoff || command == DriveCommand::STOP {
+            // controlled stop, commands are ignored until the battery recovers
+            left_motor.stop().and(right_motor.stop())
+        } else {
+            // Todo: make speed stable
+            let left = shape(command.left, expo, left_gain).clamp(-power_limit, power_limit);
+            let right = shape(command.right, expo, right_gain).clamp(-power_limit, power_limit);
+            let (left, right) = (obstacle.limit(left) as i16, obstacle.limit(right) as i16);
+            left_motor.run(left).and(right_motor.run(right))
+        };
+        if let Err(err) = driven {
+            println!("Motors: {}", err);
+            if err.recovery() == Recovery::Failsafe {
+                // the PWM can't be trusted to stop them, the pins can; until a reboot
+                crash::stop_motors();
+                command = DriveCommand::STOP;
             }
         }
 
-        Timer::after(INTERVAL).await;
+        ticker.next().await;
     }
 }
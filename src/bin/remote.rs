@@ -8,66 +8,116 @@
 #![no_std]
 #![no_main]
 
-use core::{cell::RefCell, fmt::Write};
+use core::cell::RefCell;
 
 use embassy_executor::Spawner;
-use embassy_time::{Duration, Timer};
+use embassy_futures::select::{Either, select};
+use embassy_time::{Duration, Instant, Ticker};
 use esp_alloc as _;
 use esp_backtrace as _;
 use esp_hal::{
-    analog::adc::{Adc, AdcConfig, Attenuation},
-    clock::CpuClock,
-    rng::Rng,
-    timer::timg::TimerGroup,
+    analog::adc::{Adc, AdcCalCurve, AdcConfig, Attenuation},
+    gpio::{Input, InputConfig, Pull},
+    peripherals::ADC1,
 };
 use esp_println::println;
-use esp_wifi::{EspWifiController, esp_now::PeerInfo, init};
-use heapless::String;
-use robo_remote::{self as _, Map, mk_static};
+use esp_wifi::esp_now::BROADCAST_ADDRESS;
+use robo_remote::{
+    self as _, Map,
+    board::{self, Board, Role},
+    control::{
+        arbiter::DriveCommand,
+        press::{Press, PressDetector},
+        send_policy::SendPolicy,
+    },
+    drivers::battery::{BatteryConfig, BatteryMonitor, BatteryState, Chemistry},
+    protocol::{
+        hello::{Capabilities, Compatibility, Hello, Vehicle},
+        message::{Message, MessageKind, SafetyState},
+    },
+    error::{Error, Recovery},
+    transport::{
+        Inbox, PeerId, Transport, esp_now::EspNowTransport, latency::LatencyStats, send_message,
+    },
+};
 
 const ADC_SHIFT: u16 = 2144; // to obtain zero at the minimum of a joystick range
 
+// the car until paired with one
 const PEER_ADDRESS: [u8; 6] = [0x54, 0x32, 0x04, 0x32, 0xf2, 0xb8];
+const ROLE: Role = Role::Remote { peer: PEER_ADDRESS };
 
-
-const INTERVAL: Duration = Duration::from_micros(100); // reduce load on the receiver
+// joystick is sampled and sent at this rate
+const SEND_RATE_HZ: u64 = 50;
+// percents, smaller changes are not worth the airtime
+const DEADBAND: f32 = 1.0;
+// well below the car's failsafe timeout
+const KEEPALIVE: Duration = Duration::from_millis(250);
 
 const WIFI_CHANNEL: u8 = 3;
+
+// link statistics and round trips to the selected car are printed this often
+const LINK_REPORT: Duration = Duration::from_secs(1);
+// every ping is answered, that's 20 frames a second on top of the sticks; only
+// turned on while measuring latency
+const MEASURE_LATENCY: bool = false;
+const PING_INTERVAL: Duration = Duration::from_millis(100);
+// until the selected car answers, it only gets what every car knows
+const HELLO_INTERVAL: Duration = Duration::from_secs(1);
+
+// holding the select button this long stops every car, a short press arms the
+// selected car again or selects the next one
+const LONG_PRESS: Duration = Duration::from_secs(1);
+// an unacknowledged emergency stop is sent again right away
+const ESTOP_ATTEMPTS: usize = 3;
+
+// 1S Li-ion cell through a 100k/100k divider
+const BATTERY: BatteryConfig = BatteryConfig {
+    chemistry: Chemistry::LiIon,
+    cells: 1,
+    divider_ratio: 2.0,
+    filter: 0.05,
+    hysteresis: 100,
+    low_power_limit: 100.0,
+};
 // TODO: master address
 #[esp_hal_embassy::main]
 async fn main(_spawner: Spawner) -> ! {
-    esp_println::logger::init_logger_from_env();
-    let config = esp_hal::Config::default().with_cpu_clock(CpuClock::max());
-    let peripherals = esp_hal::init(config);
-
-    esp_alloc::heap_allocator!(size: 72 * 1024);
-
-    let timg0 = TimerGroup::new(peripherals.TIMG0);
-
-    let esp_wifi_ctrl = &*mk_static!(
-        EspWifiController<'static>,
-        init(
-            timg0.timer0,
-            Rng::new(peripherals.RNG),
-            peripherals.RADIO_CLK,
-        )
-        .unwrap()
-    );
-
-    let wifi = peripherals.WIFI;
-    let mut esp_now = esp_wifi::esp_now::EspNow::new(esp_wifi_ctrl, wifi).unwrap();
-    println!("esp-now version {}", esp_now.version().unwrap());
-
-    use esp_hal::timer::systimer::SystemTimer;
-    let systimer = SystemTimer::new(peripherals.SYSTIMER);
-    esp_hal_embassy::init(systimer.alarm0);
-    esp_now.set_channel(WIFI_CHANNEL).unwrap();
-    let mut data: String<64> = String::new();
+    let Board {
+        peripherals,
+        esp_now,
+        address,
+        mut rng,
+        ..
+    } = board::init(WIFI_CHANNEL, ROLE);
+
+    // hold BOOT for a second right after start-up to pair with one more car,
+    // afterwards it selects the car to drive
+    let mut button = Input::new(peripherals.GPIO9, InputConfig::default().with_pull(Pull::Up));
+    let pair = board::pair_requested(&mut button).await;
+    let (mut transport, mut cars) =
+        board::bind_link(EspNowTransport::new(esp_now, address), ROLE, pair, &mut rng).await;
+    let paired = !cars.is_empty();
+    if !paired {
+        let _ = cars.push(PEER_ADDRESS);
+    }
+    let mut selected = 0;
+    // of the selected car, as last reported
+    let mut safety = SafetyState::Armed;
+    // what the selected car understands, nothing once refused
+    let mut caps = Capabilities::DRIVE;
+    let mut greeted = false;
+    println!("Driving {:02x?}", cars[selected]);
+    button.wait_for_high().await;
+    let mut select_button = PressDetector::new(LONG_PRESS);
 
     let analog_pin = peripherals.GPIO1;
     let mut adc1_config = AdcConfig::new();
 
     let mut pin = adc1_config.enable_pin(analog_pin, Attenuation::_11dB);
+    let mut battery_pin = adc1_config
+        .enable_pin_with_cal::<_, AdcCalCurve<ADC1>>(peripherals.GPIO3, Attenuation::_11dB);
+    let mut battery = BatteryMonitor::new(BATTERY);
 
     let adc = RefCell::new(peripherals.ADC1);
     let mut adc1 = Adc::new(adc.borrow_mut(), adc1_config).into_async();
@@ -77,16 +127,19 @@ async fn main(_spawner: Spawner) -> ! {
     let mut pin2 = adc12_config.enable_pin(analog_pin2, Attenuation::_11dB);
     let mut adc12 = Adc::new(adc.borrow_mut(), adc12_config);
 
+    let mut left = SendPolicy::new(DEADBAND, KEEPALIVE);
+    let mut right = SendPolicy::new(DEADBAND, KEEPALIVE);
+    let mut ticker = Ticker::every(Duration::from_hz(SEND_RATE_HZ));
+    let mut next_report = Instant::now() + LINK_REPORT;
+    let mut next_ping = Instant::now();
+    let mut next_hello = Instant::now();
+    let mut latency = LatencyStats::default();
+    let mut inbox = Inbox::new();
     loop {
-        if !esp_now.peer_exists(&PEER_ADDRESS) {
-            esp_now
-                .add_peer(PeerInfo {
-                    peer_address: PEER_ADDRESS,
-                    lmk: None,
-                    channel: None,
-                    encrypt: false,
-                })
-                .unwrap();
+        let previous = battery.state();
+        let state = battery.update(adc1.read_oneshot(&mut battery_pin).await);
+        if state != previous && state != BatteryState::Normal {
+            println!("Remote battery low: {}%", battery.state_of_charge());
         }
 
         let x = adc1.read_oneshot(&mut pin).await.saturating_sub(ADC_SHIFT);
@@ -107,14 +160,166 @@ async fn main(_spawner: Spawner) -> ! {
         println!("X normed: {}", x);
         println!("Y normed: {}", y);
 
-        data.clear();
-        writeln!(&mut data, "LSPEED:{};", x).unwrap(); // todo
-        let status = esp_now.send_async(&PEER_ADDRESS, data.as_bytes()).await;
-        println!("Send broadcast status: {:?}", status);
-        data.clear();
-        writeln!(&mut data, "RSPEED:{};", y).unwrap(); // todo
-        let status = esp_now.send_async(&PEER_ADDRESS, data.as_bytes()).await;
-        println!("Send broadcast status: {:?}", status);
-        Timer::after(INTERVAL).await;
+        let now = Instant::now();
+        match select_button.update(button.is_low(), now) {
+            Some(Press::Short) if safety == SafetyState::EmergencyStopped => {
+                if (DriveCommand { left: x, right: y }).is_neutral() {
+                    // pressed again if it didn't arm
+                    let _ = send(&mut transport, &cars[selected], caps, &Message::Arm).await;
+                } else {
+                    println!("Centre the sticks to arm");
+                }
+            }
+            Some(Press::Short) if cars.len() > 1 => {
+                // the car left behind stops and is free for another remote
+                // otherwise its failsafe releases it
+                let _ = send(&mut transport, &cars[selected], caps, &Message::Release).await;
+                selected = (selected + 1) % cars.len();
+                safety = SafetyState::Armed;
+                caps = Capabilities::DRIVE;
+                greeted = false;
+                next_hello = now;
+                latency.reset();
+                left = SendPolicy::new(DEADBAND, KEEPALIVE);
+                right = SendPolicy::new(DEADBAND, KEEPALIVE);
+                println!("Driving {:02x?}", cars[selected]);
+            }
+            Some(Press::Long) => {
+                println!("Emergency stop");
+                // a broadcast can't be encrypted, paired cars only hear unicasts
+                let all = if paired { &cars[..] } else { &[BROADCAST_ADDRESS][..] };
+                // whatever the cars said in their hellos
+                let every = Capabilities::ALL;
+                for car in all {
+                    for _ in 0..ESTOP_ATTEMPTS {
+                        match send(&mut transport, car, every, &Message::EmergencyStop).await {
+                            Err(err) if err.recovery() == Recovery::Retry => continue,
+                            _ => break,
+                        }
+                    }
+                }
+                safety = SafetyState::EmergencyStopped;
+            }
+            _ => (),
+        }
+        let car = cars[selected];
+
+        if !greeted && now >= next_hello {
+            next_hello = now + HELLO_INTERVAL;
+            let hello = Message::Hello(Hello::local(Vehicle::Remote));
+            let _ = send(&mut transport, &car, Capabilities::ALL, &hello).await;
+        }
+
+        if now >= next_report {
+            next_report = now + LINK_REPORT;
+            let metrics = transport.metrics();
+            println!(
+                "Link: sent {} failed {} received {} lost {} rssi {:?}",
+                metrics.sent, metrics.send_failed, metrics.received, metrics.lost, metrics.rssi
+            );
+            if let Some(rtt) = latency.summary() {
+                println!(
+                    "Round trip over {} pings: min {} avg {} max {} jitter {} us",
+                    rtt.samples,
+                    rtt.min.as_micros(),
+                    rtt.avg.as_micros(),
+                    rtt.max.as_micros(),
+                    rtt.jitter.as_micros()
+                );
+            }
+            latency.reset();
+        }
+        // only once the car said it answers pings
+        if MEASURE_LATENCY && greeted && caps.supports(MessageKind::Ping) && now >= next_ping {
+            next_ping = now + PING_INTERVAL;
+            // the car echoes our clock, so nothing has to be remembered
+            let ping = Message::Ping(now.as_micros() as u32);
+            let _ = send(&mut transport, &car, caps, &ping).await;
+        }
+
+        // failed sends go again with the next sample instead of waiting for the keepalive
+        if left.should_send(x, now) {
+            if let Err(err) = send(&mut transport, &car, caps, &Message::LeftSpeed(x)).await {
+                if err.recovery() == Recovery::Retry {
+                    left.failed();
+                }
+            }
+        }
+        if right.should_send(y, now) {
+            if let Err(err) = send(&mut transport, &car, caps, &Message::RightSpeed(y)).await {
+                if err.recovery() == Recovery::Retry {
+                    right.failed();
+                }
+            }
+        }
+
+        // car telemetry until the next sample is due
+        while let Either::Second(received) =
+            select(ticker.next(), inbox.receive(&mut transport)).await
+        {
+            match received {
+                Ok((src, Message::Battery(charge))) => {
+                    println!("Car {:02x?} battery: {}%", src, charge)
+                }
+                Ok((src, Message::Owner(owner))) if owner != Some(address) => {
+                    println!("Car {:02x?} is controlled by {:02x?}", src, owner)
+                }
+                Ok((src, Message::Pong(sent))) if src == cars[selected] => {
+                    let rtt = (Instant::now().as_micros() as u32).wrapping_sub(sent);
+                    latency.record(Duration::from_micros(rtt as u64));
+                }
+                Ok((src, Message::Rssi(rssi))) => println!("Car {:02x?} rssi: {} dBm", src, rssi),
+                Ok((src, Message::Loss(loss))) => println!("Car {:02x?} loss: {}%", src, loss),
+                Ok((src, Message::Crash(record))) => {
+                    println!("Car {:02x?} restarted after a crash: {}", src, record)
+                }
+                Ok((src, Message::Hello(hello))) if src == cars[selected] => {
+                    greeted = true;
+                    caps = match hello.compatibility() {
+                        Compatibility::Full => Capabilities::ALL,
+                        Compatibility::Degraded => {
+                            println!("Car {:02x?} runs {:?}, sending what it knows", src, hello);
+                            hello.messages.common(Capabilities::ALL)
+                        }
+                        Compatibility::Incompatible => {
+                            println!("Car {:02x?} runs {:?}, not driving it", src, hello);
+                            Capabilities::NONE
+                        }
+                    };
+                    // printed below, for tuning over the serial console
+                    if caps.supports(MessageKind::ParamList) {
+                        let _ = send(&mut transport, &src, caps, &Message::ParamList).await;
+                    }
+                }
+                Ok((src, Message::Param(param))) => println!(
+                    "Car {:02x?} parameter {}: {} = {}",
+                    src, param.id, param.name, param.value
+                ),
+                Ok((src, Message::Safety(state))) if src == cars[selected] => {
+                    if state != safety {
+                        println!("Car {:02x?}: {}", src, state.as_str());
+                    }
+                    safety = state;
+                }
+                _ => (),
+            }
+        }
+    }
+}
+
+async fn send<T: Transport>(
+    transport: &mut T,
+    car: &PeerId,
+    caps: Capabilities,
+    message: &Message,
+) -> Result<(), Error> {
+    // the car wouldn't understand it, expected with older cars so it isn't printed
+    if !caps.supports(message.kind()) {
+        return Err(Error::Unsupported);
+    }
+    let res = send_message(transport, car, message).await;
+    if let Err(err) = &res {
+        println!("Sending {:?} to {:02x?} failed: {}", message, car, err);
     }
+    res
 }
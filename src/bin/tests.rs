@@ -5,6 +5,8 @@
 use function_name::named;
 
 use embassy_executor::Spawner;
+use embassy_futures::{block_on, join::join, select::select};
+use embassy_time::{Duration, Instant, Timer};
 use esp_alloc as _;
 use esp_backtrace as _;
 use esp_hal::
@@ -13,26 +15,63 @@ use esp_hal::
 
 use esp_println::println;
 
-use robo_remote::protocol::{
-    message::Message,
-    parser::{ParsingError, parse},
+use core::{
+    fmt::Write,
+    sync::atomic::{AtomicU32, Ordering},
+};
+
+use heapless::{String, Vec};
+use robo_remote::{
+    config::{Binding, Config, MAX_BINDINGS},
+    control::{
+        arbiter::{Arbiter, DriveCommand},
+        arming::ArmingGate,
+        obstacle::ObstacleLimiter,
+        owner::Ownership,
+        press::{Press, PressDetector},
+        send_policy::SendPolicy,
+        supervisor::{Heartbeat, Supervisor},
+    },
+    drivers::battery::{BatteryConfig, BatteryMonitor, BatteryState, Chemistry},
+    error::{Error, Recovery},
+    pairing::{derive_keys, pair_car, pair_remote},
+    params::{ParamDef, ParamError, ParamTable},
+    protocol::{
+        auth::{Authenticator, TRAILER_LEN},
+        hello::{Capabilities, Compatibility, Hello, PROTOCOL_VERSION, Vehicle},
+        message::{CRASH_LEN, Message, MessageKind, SafetyState},
+        param::{ParamFlags, ParamInfo, ParamType, ParamValue},
+        parser::{ParsingError, Token, parse, parse_all},
+        stream::StreamParser,
+        tokenizer::{RawMessage, Tokenizer},
+    },
+    transport::{
+        Inbox, LinkMetrics, Transport, TransportError, latency::LatencyStats,
+        loopback::LoopbackLink, send_message, signed::SignedTransport,
+    },
+};
+#[cfg(feature = "web")]
+use robo_remote::web::{
+    http::parse_request,
+    websocket::{Opcode, accept_key, decode, encode},
 };
 
 use esp_hal::timer::systimer::SystemTimer;
+use x25519_dalek::{PublicKey, StaticSecret};
 
 
 #[named]
 fn parse_chassis_test() {
     println!("{}", function_name!());
-    let message = "LSPEED:25.0;";
+    let message = b"LSPEED:25.0;";
     let res = parse(message);
     assert_eq(res, Ok(Message::LeftSpeed(25.0)));
 
-    let message = "RSPEED:25.08;";
+    let message = b"RSPEED:25.08;";
     let res = parse(message);
     assert_eq(res, Ok(Message::RightSpeed(25.08)));
 
-    let message = "STOP:;";
+    let message = b"STOP:;";
     let res = parse(message);
     assert_eq(res, Ok(Message::Stop));
 
@@ -42,17 +81,17 @@ fn parse_chassis_test() {
 #[named]
 fn parse_value_error_test() {
     println!("{}", function_name!());
-    let message = "LSPEED:;";
+    let message = b"LSPEED:;";
     let res = parse(message);
-    assert_eq(res, Err(ParsingError::ValueCanNotBeParsed));
+    assert_eq(res, Err(ParsingError::ValueCanNotBeParsed { offset: 7, token: Token::new(b"") }));
     println!("PASSED");
 }
 #[named]
 fn parse_not_a_comand_error_test() {
     println!("{}", function_name!());
-    let message = "STO:;";
+    let message = b"STO:;";
     let res = parse(message);
-    assert_eq(res, Err(ParsingError::NotAComand));
+    assert_eq(res, Err(ParsingError::NotAComand { offset: 0, token: Token::new(b"STO") }));
 
     println!("PASSED");
 }
@@ -60,14 +99,1218 @@ fn parse_not_a_comand_error_test() {
 #[named]
 fn parse_sepparator_error_test() {
     println!("{}", function_name!());
-    let message = "LSPEED;";
+    let message = b"LSPEED;";
     let res = parse(message);
-    assert_eq(res, Err(ParsingError::NoSepparator));
+    assert_eq(res, Err(ParsingError::NoSepparator { offset: 0, expected: ':' }));
 
-    let message = "LSPEED:4";
+    let message = b"LSPEED:4";
     let res = parse(message);
-    assert_eq(res, Err(ParsingError::NoSepparator));
+    assert_eq(res, Err(ParsingError::NoSepparator { offset: 0, expected: ';' }));
+
+    println!("PASSED");
+}
+
+#[named]
+fn parse_whitespace_test() {
+    println!("{}", function_name!());
+    assert_eq(parse(b" LSPEED : 25.0 ;\r\n"), Ok(Message::LeftSpeed(25.0)));
+    assert_eq(parse(b"\r\nBATT:80;"), Ok(Message::Battery(80)));
+    assert_eq(parse(b"\tSTOP:\t;\n"), Ok(Message::Stop));
+    assert_eq(parse(b"OWNER:  ;"), Ok(Message::Owner(None)));
+
+    // whitespace inside a key or value is kept
+    let res = parse(b"ST OP:;");
+    assert_eq(res, Err(ParsingError::NotAComand { offset: 0, token: Token::new(b"ST OP") }));
+    let res = parse(b"LSPEED: 2 5;");
+    assert_eq(res, Err(ParsingError::ValueCanNotBeParsed { offset: 8, token: Token::new(b"2 5") }));
+
+    // nothing but whitespace
+    assert_eq(parse(b""), Err(ParsingError::NoSepparator { offset: 0, expected: ';' }));
+    assert_eq(parse(b" \r\n\t"), Err(ParsingError::NoSepparator { offset: 4, expected: ';' }));
+    assert_eq(parse(b";"), Err(ParsingError::NoSepparator { offset: 0, expected: ':' }));
+    assert_eq(parse(b"  :;"), Err(ParsingError::NotAComand { offset: 2, token: Token::new(b"") }));
+
+    println!("PASSED");
+}
+
+#[named]
+fn parse_non_ascii_test() {
+    println!("{}", function_name!());
+    // 'É' is two bytes in UTF-8, offsets still point at the bytes
+    let res = parse("ÉSTOP:;".as_bytes());
+    assert_eq(res, Err(ParsingError::NotAscii { offset: 0, byte: 0xc3 }));
+    let res = parse(b"LSPEED:2\xff5;");
+    assert_eq(res, Err(ParsingError::NotAscii { offset: 8, byte: 0xff }));
+    let res = parse(b"ST\0OP:;");
+    assert_eq(res, Err(ParsingError::NotAscii { offset: 2, byte: 0 }));
+    let res = parse(b"LSPEED:\x80");
+    assert_eq(res, Err(ParsingError::NotAscii { offset: 7, byte: 0x80 }));
+    // only the first message is parsed
+    assert_eq(parse(b"STOP:;\xff"), Ok(Message::Stop));
+
+    // every byte value as the value of STOP, which takes none
+    for byte in 0..=u8::MAX {
+        let frame = [b'S', b'T', b'O', b'P', b':', byte, b';'];
+        let expected = match byte {
+            b' ' | b'\t' | b'\r' | b'\n' | b';' => Ok(Message::Stop),
+            b'!'..=b'~' => {
+                let token = Token::new(&[byte]);
+                Err(ParsingError::ValueCanNotBeParsed { offset: 5, token })
+            }
+            _ => Err(ParsingError::NotAscii { offset: 5, byte }),
+        };
+        assert_eq(parse(&frame), expected);
+    }
+
+    // every cut of a valid frame
+    let frame = b"LSPEED:25.0;";
+    for len in 0..frame.len() {
+        let res = parse(&frame[..len]);
+        assert_eq(res, Err(ParsingError::NoSepparator { offset: 0, expected: ';' }));
+    }
+    assert_eq(parse(frame), Ok(Message::LeftSpeed(25.0)));
+
+    println!("PASSED");
+}
+
+#[named]
+fn tokenizer_test() {
+    println!("{}", function_name!());
+    let mut tokens = Tokenizer::new(b"STOP:;\nX;BATT: 80 ;\xff");
+    let stop = RawMessage { key: b"STOP", key_offset: 0, value: b"", value_offset: 5 };
+    assert_eq(tokens.next(), Some(Ok(stop)));
+    assert_eq(tokens.next(), Some(Err(ParsingError::NoSepparator { offset: 7, expected: ':' })));
+    let battery = RawMessage { key: b"BATT", key_offset: 9, value: b"80", value_offset: 15 };
+    assert_eq(tokens.next(), Some(Ok(battery)));
+    assert_eq(tokens.position(), 19);
+    assert_eq(tokens.next(), Some(Err(ParsingError::NotAscii { offset: 19, byte: 0xff })));
+    assert_eq(tokens.next(), None);
+    assert_eq(tokens.position(), 20);
+
+    assert_eq(Tokenizer::new(b"").next(), None);
+    assert_eq(Tokenizer::new(b"\r\n \t").next(), None);
+    assert_eq(Tokenizer::new(b"A:1;B:2;\r\n").count(), 2);
+
+    let mut text: String<64> = String::new();
+    write!(&mut text, "{}", ParsingError::NotAscii { offset: 3, byte: 0xc3 }).unwrap();
+    assert_eq(text.as_str(), "unexpected byte 0xc3 at byte 3");
+
+    println!("PASSED");
+}
+
+#[named]
+fn parse_validation_test() {
+    println!("{}", function_name!());
+    assert_eq(parse(b"LSPEED:-100;"), Ok(Message::LeftSpeed(-100.0)));
+    assert_eq(parse(b"RSPEED:100.0;"), Ok(Message::RightSpeed(100.0)));
+    for (frame, token) in [
+        (b"LSPEED:NaN;".as_slice(), b"NaN".as_slice()),
+        (b"LSPEED:inf;", b"inf"),
+        (b"LSPEED:-infinity;", b"-infinity"),
+        (b"LSPEED:100.5;", b"100.5"),
+        (b"LSPEED:1e9;", b"1e9"),
+    ] {
+        let token = Token::new(token);
+        assert_eq(parse(frame), Err(ParsingError::OutOfRange { offset: 7, token }));
+    }
+    let res = parse(b"RSPEED:-101;");
+    assert_eq(res, Err(ParsingError::OutOfRange { offset: 7, token: Token::new(b"-101") }));
+
+    assert_eq(parse(b"BATT:100;"), Ok(Message::Battery(100)));
+    let res = parse(b"BATT:101;");
+    assert_eq(res, Err(ParsingError::OutOfRange { offset: 5, token: Token::new(b"101") }));
+    let res = parse(b"LOSS:255;");
+    assert_eq(res, Err(ParsingError::OutOfRange { offset: 5, token: Token::new(b"255") }));
+    let res = parse(b"RSSI:12;");
+    assert_eq(res, Err(ParsingError::OutOfRange { offset: 5, token: Token::new(b"12") }));
+
+    // commands without a value reject one
+    let garbage = ParsingError::ValueCanNotBeParsed { offset: 5, token: Token::new(b"garbage") };
+    assert_eq(parse(b"STOP:garbage;"), Err(garbage));
+    let res = parse(b"ARM: 1 ;");
+    assert_eq(res, Err(ParsingError::ValueCanNotBeParsed { offset: 5, token: Token::new(b"1") }));
+    assert_eq(parse(b"ESTOP: ;"), Ok(Message::EmergencyStop));
+
+    let mut text: String<64> = String::new();
+    write!(&mut text, "{}", parse(b"LSPEED:inf;").unwrap_err()).unwrap();
+    assert_eq(text.as_str(), "value \"inf\" at byte 7 out of range");
+    println!("PASSED");
+}
+
+#[named]
+fn parse_diagnostics_test() {
+    println!("{}", function_name!());
+    let mut text: String<64> = String::new();
+    let err = parse(b"LSPEED:fast;").unwrap_err();
+    write!(&mut text, "{}", err).unwrap();
+    assert_eq(text.as_str(), "bad value \"fast\" at byte 7");
+
+    // non-printable bytes are escaped, long tokens cut
+    text.clear();
+    let err = parse(b"SP\tEED:1;").unwrap_err();
+    write!(&mut text, "{}", err).unwrap();
+    assert_eq(text.as_str(), "unknown command \"SP\\x09EED\" at byte 0");
+    text.clear();
+    write!(&mut text, "{:?}", Token::new(b"0123456789abcdefXYZ")).unwrap();
+    assert_eq(text.as_str(), "\"0123456789abcdef...\"");
+
+    text.clear();
+    write!(&mut text, "{}", parse(b"STOP").unwrap_err()).unwrap();
+    assert_eq(text.as_str(), "no ';' from byte 0 on");
+    println!("PASSED");
+}
+
+#[named]
+fn parse_battery_test() {
+    println!("{}", function_name!());
+    let message = b"BATT:87;";
+    let res = parse(message);
+    assert_eq(res, Ok(Message::Battery(87)));
+
+    let message = b"BATT:-1;";
+    let res = parse(message);
+    assert_eq(res, Err(ParsingError::ValueCanNotBeParsed { offset: 5, token: Token::new(b"-1") }));
+
+    println!("PASSED");
+}
+
+#[named]
+fn battery_hysteresis_test() {
+    println!("{}", function_name!());
+    let mut battery = BatteryMonitor::new(BatteryConfig {
+        chemistry: Chemistry::LiIon,
+        cells: 1,
+        divider_ratio: 2.0,
+        filter: 1.0,
+        hysteresis: 100,
+        low_power_limit: 50.0,
+    });
+
+    assert_eq(battery.update(2000), BatteryState::Normal);
+    assert_eq(battery.state_of_charge(), 77);
+    assert_eq(battery.update(1740), BatteryState::Low);
+    assert_eq(battery.power_limit(), 50.0);
+    // inside the hysteresis band
+    assert_eq(battery.update(1790), BatteryState::Low);
+    assert_eq(battery.update(1640), BatteryState::Cutoff);
+    assert_eq(battery.power_limit(), 0.0);
+    // latched while the unloaded pack recovers
+    assert_eq(battery.update(1680), BatteryState::Cutoff);
+    assert_eq(battery.update(1710), BatteryState::Cutoff);
+    assert_eq(battery.update(1810), BatteryState::Cutoff);
+    assert_eq(battery.power_limit(), 0.0);
+    // charged
+    assert_eq(battery.update(2060), BatteryState::Normal);
+
+    println!("PASSED");
+}
+
+#[named]
+fn obstacle_approach_test() {
+    println!("{}", function_name!());
+    let mut obstacle = ObstacleLimiter::new(20.0, 60.0, 10.0, Duration::from_millis(200));
+    // (distance, expected forward speed for a full forward command), a reading every 60 ms
+    let script = [
+        (None, 100.0),
+        (Some(100.0), 100.0),
+        (Some(60.0), 100.0),
+        (Some(40.0), 50.0),
+        (Some(30.0), 25.0),
+        (Some(20.0), 0.0),
+        (Some(10.0), 0.0),
+    ];
+    for (i, (distance, expected)) in script.into_iter().enumerate() {
+        obstacle.update(distance, Instant::from_millis(i as u64 * 60));
+        assert_eq(obstacle.limit(100.0), expected);
+    }
+    println!("PASSED");
+}
+
+#[named]
+fn obstacle_release_test() {
+    println!("{}", function_name!());
+    let mut obstacle = ObstacleLimiter::new(20.0, 60.0, 10.0, Duration::from_millis(200));
+    let script = [
+        (Some(15.0), 0.0),
+        // still inside the hysteresis band
+        (Some(25.0), 0.0),
+        (Some(30.0), 0.0),
+        (Some(40.0), 50.0),
+        (Some(25.0), 12.5),
+        (Some(18.0), 0.0),
+    ];
+    for (i, (distance, expected)) in script.into_iter().enumerate() {
+        obstacle.update(distance, Instant::from_millis(i as u64 * 60));
+        assert_eq(obstacle.limit(100.0), expected);
+    }
+    println!("PASSED");
+}
+
+#[named]
+fn obstacle_dropout_test() {
+    println!("{}", function_name!());
+    let mut obstacle = ObstacleLimiter::new(20.0, 60.0, 10.0, Duration::from_millis(200));
+    let at = Instant::from_millis;
+    // (ms, distance, expected forward speed)
+    let script = [
+        (0, Some(15.0), 0.0),
+        // a missed echo right at the wall keeps it blocked
+        (60, None, 0.0),
+        (120, Some(14.0), 0.0),
+        (180, None, 0.0),
+        (240, None, 0.0),
+        (300, None, 0.0),
+        // nothing echoed for the whole dropout
+        (360, None, 100.0),
+        (420, Some(40.0), 50.0),
+        // a slowed down car stays slow too
+        (480, None, 50.0),
+        (540, Some(30.0), 25.0),
+        (600, None, 25.0),
+        (800, None, 100.0),
+        (860, None, 100.0),
+    ];
+    for (ms, distance, expected) in script {
+        obstacle.update(distance, at(ms));
+        assert_eq(obstacle.limit(100.0), expected);
+    }
+    println!("PASSED");
+}
+
+#[named]
+fn obstacle_reverse_test() {
+    println!("{}", function_name!());
+    let mut obstacle = ObstacleLimiter::new(20.0, 60.0, 10.0, Duration::from_millis(200));
+    for (i, distance) in [Some(5.0), Some(30.0), Some(45.0), None].into_iter().enumerate() {
+        obstacle.update(distance, Instant::from_millis(i as u64 * 60));
+        assert_eq(obstacle.limit(-80.0), -80.0);
+        assert_eq(obstacle.limit(0.0), 0.0);
+    }
+    println!("PASSED");
+}
+
+#[named]
+fn arbiter_test() {
+    println!("{}", function_name!());
+    let mut arbiter = Arbiter::default();
+    arbiter.handle(&Message::LeftSpeed(40.0));
+    let command = arbiter.handle(&Message::RightSpeed(250.0));
+    assert_eq(command, DriveCommand { left: 40.0, right: 100.0 });
+
+    // telemetry doesn't change the command
+    assert_eq(arbiter.handle(&Message::Battery(50)), command);
+    assert_eq(arbiter.handle(&Message::Stop), DriveCommand::STOP);
+
+    arbiter.handle(&Message::LeftSpeed(-30.0));
+    assert_eq(arbiter.handle(&Message::Release), DriveCommand::STOP);
+
+    arbiter.handle(&Message::LeftSpeed(-30.0));
+    assert_eq(arbiter.failsafe(), DriveCommand::STOP);
+    assert_eq(arbiter.command(), DriveCommand::STOP);
+    println!("PASSED");
+}
+
+#[named]
+fn emergency_stop_test() {
+    println!("{}", function_name!());
+    let mut arbiter = Arbiter::default();
+    arbiter.handle(&Message::LeftSpeed(40.0));
+    assert_eq(arbiter.handle(&Message::EmergencyStop), DriveCommand::STOP);
+    assert_eq(arbiter.safety(), SafetyState::EmergencyStopped);
+
+    // latched: neither new speeds nor the failsafe release it
+    assert_eq(arbiter.handle(&Message::RightSpeed(40.0)), DriveCommand::STOP);
+    assert_eq(arbiter.failsafe(), DriveCommand::STOP);
+    arbiter.handle(&Message::LeftSpeed(40.0));
+    assert_eq(arbiter.handle(&Message::Arm), DriveCommand::STOP);
+    assert_eq(arbiter.safety(), SafetyState::EmergencyStopped);
+
+    // centred sticks
+    arbiter.handle(&Message::LeftSpeed(3.0));
+    assert_eq(arbiter.handle(&Message::Arm), DriveCommand { left: 3.0, right: 0.0 });
+    assert_eq(arbiter.safety(), SafetyState::Armed);
+    assert_eq(arbiter.handle(&Message::LeftSpeed(50.0)), DriveCommand { left: 50.0, right: 0.0 });
+    println!("PASSED");
+}
+
+#[named]
+fn emergency_stop_latched_test() {
+    println!("{}", function_name!());
+    // as the car boots after a reset with the latch set
+    let mut arbiter = Arbiter::latched();
+    assert_eq(arbiter.safety(), SafetyState::EmergencyStopped);
+    assert_eq(arbiter.handle(&Message::LeftSpeed(40.0)), DriveCommand::STOP);
+    assert_eq(arbiter.handle(&Message::Arm), DriveCommand::STOP);
+
+    arbiter.handle(&Message::LeftSpeed(0.0));
+    assert_eq(arbiter.handle(&Message::Arm), DriveCommand::STOP);
+    assert_eq(arbiter.safety(), SafetyState::Armed);
+    println!("PASSED");
+}
+
+#[named]
+fn arming_test() {
+    println!("{}", function_name!());
+    let at = Instant::from_millis;
+    let forward = DriveCommand { left: 60.0, right: 60.0 };
+    let centred = DriveCommand { left: 2.0, right: -1.0 };
+    let mut gate = ArmingGate::new(Duration::from_millis(500));
+
+    // deflected sticks at boot are ignored and restart the hold
+    assert_eq(gate.update(forward, at(0)), DriveCommand::STOP);
+    assert_eq(gate.update(centred, at(100)), DriveCommand::STOP);
+    assert_eq(gate.update(forward, at(400)), DriveCommand::STOP);
+    assert_eq(gate.update(centred, at(500)), DriveCommand::STOP);
+    assert_eq(gate.update(centred, at(900)), DriveCommand::STOP);
+    assert_eq(gate.is_armed(), false);
+    assert_eq(gate.update(centred, at(1000)), centred);
+    assert_eq(gate.is_armed(), true);
+    assert_eq(gate.update(forward, at(1020)), forward);
+
+    // after a failsafe it starts over
+    gate.disarm();
+    assert_eq(gate.update(forward, at(3000)), DriveCommand::STOP);
+    assert_eq(gate.update(centred, at(3100)), DriveCommand::STOP);
+    assert_eq(gate.update(centred, at(3600)), centred);
+    println!("PASSED");
+}
+
+#[named]
+fn parse_safety_test() {
+    println!("{}", function_name!());
+    assert_eq(parse(b"ESTOP:;"), Ok(Message::EmergencyStop));
+    assert_eq(parse(b"ARM:;"), Ok(Message::Arm));
+    assert_eq(parse(b"SAFETY:ESTOP;"), Ok(Message::Safety(SafetyState::EmergencyStopped)));
+    assert_eq(parse(b"SAFETY:ARMED;"), Ok(Message::Safety(SafetyState::Armed)));
+    assert_eq(parse(b"SAFETY:DISARMED;"), Ok(Message::Safety(SafetyState::Disarmed)));
+    let maybe = ParsingError::ValueCanNotBeParsed { offset: 7, token: Token::new(b"MAYBE") };
+    assert_eq(parse(b"SAFETY:MAYBE;"), Err(maybe));
+
+    let mut data: String<32> = String::new();
+    write!(&mut data, "{}", Message::Safety(SafetyState::EmergencyStopped)).unwrap();
+    assert_eq(data.as_str(), "SAFETY:ESTOP;");
+    println!("PASSED");
+}
+
+#[named]
+fn send_policy_test() {
+    println!("{}", function_name!());
+    let mut policy = SendPolicy::new(1.0, Duration::from_millis(250));
+    // (time in ms, value, expected to be sent)
+    let script = [
+        (0, 10.0, true),
+        (20, 10.5, false),
+        (40, 12.0, true),
+        (60, 11.5, false),
+        (280, 11.5, false),
+        (300, 11.5, true),
+        (320, 0.5, true),
+        (340, 0.0, true),
+        (360, 0.0, false),
+    ];
+    for (ms, value, expected) in script {
+        assert_eq(policy.should_send(value, Instant::from_millis(ms)), expected);
+    }
+
+    // a failed send doesn't count as sent
+    policy.failed();
+    assert_eq(policy.should_send(0.0, Instant::from_millis(380)), true);
+    println!("PASSED");
+}
+
+#[named]
+fn error_test() {
+    println!("{}", function_name!());
+    assert_eq(Error::from(TransportError::Send).recovery(), Recovery::Retry);
+    assert_eq(Error::from(TransportError::Peer).recovery(), Recovery::Drop);
+    assert_eq(Error::from(ParsingError::BadTag).recovery(), Recovery::Drop);
+    assert_eq(Error::Driver.recovery(), Recovery::Failsafe);
+    assert_eq(Error::Unsupported.recovery(), Recovery::Drop);
+
+    let link = LoopbackLink::new();
+    let (mut remote, mut car) = link.endpoints(REMOTE, CAR);
+    let mut inbox = Inbox::new();
+    block_on(async {
+        send_message(&mut remote, &CAR, &Message::LeftSpeed(25.0)).await.unwrap();
+        assert_eq(inbox.receive(&mut car).await, Ok((REMOTE, Message::LeftSpeed(25.0))));
+
+        // the loopback holds 4 frames, the 5th is a transient failure
+        for _ in 0..4 {
+            send_message(&mut remote, &CAR, &Message::Stop).await.unwrap();
+        }
+        let full = send_message(&mut remote, &CAR, &Message::Stop).await;
+        assert_eq(full, Err(Error::Transport(TransportError::Send)));
+    });
+    println!("PASSED");
+}
+
+const REMOTE: [u8; 6] = [0x02, 0, 0, 0, 0, 0x01];
+const CAR: [u8; 6] = [0x02, 0, 0, 0, 0, 0x02];
+const OTHER_CAR: [u8; 6] = [0x02, 0, 0, 0, 0, 0x03];
+
+#[named]
+fn loopback_transport_test() {
+    println!("{}", function_name!());
+    let link = LoopbackLink::new();
+    let (mut remote, mut car) = link.endpoints(REMOTE, CAR);
+
+    block_on(async {
+        assert_eq(remote.send(&CAR, b"LSPEED:10;").await, Ok(()));
+        assert_eq(car.receive().await.map(|frame| frame.peer), Ok(REMOTE));
+
+        assert_eq(car.send(&REMOTE, b"BATT:80;").await, Ok(()));
+        let frame = remote.receive().await.unwrap();
+        assert_eq(frame.data.as_slice(), b"BATT:80;".as_slice());
+    });
+
+    assert_eq(remote.metrics().sent, 1);
+    assert_eq(remote.metrics().received, 1);
+    assert_eq(car.local_id(), CAR);
+    println!("PASSED");
+}
+
+#[named]
+fn loopback_drives_arbiter_test() {
+    println!("{}", function_name!());
+    let link = LoopbackLink::new();
+    let (mut remote, mut car) = link.endpoints(REMOTE, CAR);
+    let mut arbiter = Arbiter::default();
+    let mut inbox = Inbox::new();
+
+    block_on(async {
+        remote.send(&CAR, b"LSPEED:30;").await.unwrap();
+        // unparsable frames are skipped
+        remote.send(&CAR, b"garbage").await.unwrap();
+        remote.send(&CAR, b"RSPEED:-20;").await.unwrap();
+
+        for _ in 0..2 {
+            let (src, message) = inbox.receive(&mut car).await.unwrap();
+            assert_eq(src, REMOTE);
+            arbiter.handle(&message);
+        }
+    });
+
+    assert_eq(arbiter.command(), DriveCommand { left: 30.0, right: -20.0 });
+    println!("PASSED");
+}
+
+#[named]
+fn parse_all_test() {
+    println!("{}", function_name!());
+    let mut messages = parse_all(b"LSPEED:10;RSPEED:10;");
+    assert_eq(messages.next(), Some(Ok(Message::LeftSpeed(10.0))));
+    assert_eq(messages.next(), Some(Ok(Message::RightSpeed(10.0))));
+    assert_eq(messages.next(), None);
+
+    // a bad message doesn't take the rest of the frame with it
+    let mut messages = parse_all(b"STOP:;\r\nSTO:;\r\nBATT:80;\r\nLSPEED:1");
+    assert_eq(messages.next(), Some(Ok(Message::Stop)));
+    let unknown = ParsingError::NotAComand { offset: 8, token: Token::new(b"STO") };
+    assert_eq(messages.next(), Some(Err(unknown)));
+    assert_eq(messages.next(), Some(Ok(Message::Battery(80))));
+    let cut = ParsingError::NoSepparator { offset: 25, expected: ';' };
+    assert_eq(messages.next(), Some(Err(cut)));
+    assert_eq(messages.next(), None);
+    println!("PASSED");
+}
+
+#[named]
+fn inbox_test() {
+    println!("{}", function_name!());
+    let link = LoopbackLink::new();
+    let (mut remote, mut car) = link.endpoints(REMOTE, CAR);
+    let mut inbox = Inbox::new();
+
+    block_on(async {
+        remote.send(&CAR, b"LSPEED:10;RSPEED:-10;").await.unwrap();
+        remote.send(&CAR, b"garbage;ARM:;").await.unwrap();
+        for expected in [
+            Message::LeftSpeed(10.0),
+            Message::RightSpeed(-10.0),
+            Message::Arm,
+        ] {
+            assert_eq(inbox.receive(&mut car).await, Ok((REMOTE, expected)));
+        }
+    });
+    assert_eq(car.metrics().received, 2);
+    println!("PASSED");
+}
+
+#[named]
+fn stream_parser_test() {
+    println!("{}", function_name!());
+    let mut stream: StreamParser<16> = StreamParser::new();
+    let mut results: Vec<Result<Message, ParsingError>, 4> = Vec::new();
+    // messages cut anywhere between reads
+    for read in [b"LSPEED:1".as_slice(), b"0;RSP", b"EED:-5;\r\n", b"\r\nST", b"OP"] {
+        for &byte in read {
+            if let Some(res) = stream.push(byte) {
+                results.push(res).unwrap();
+            }
+        }
+    }
+    let expected = [Ok(Message::LeftSpeed(10.0)), Ok(Message::RightSpeed(-5.0))];
+    assert_eq(results.as_slice(), expected.as_slice());
+    assert_eq(stream.pending(), 4);
+
+    // a message longer than the buffer is dropped up to its ';'
+    let mut stream: StreamParser<8> = StreamParser::new();
+    results.clear();
+    for &byte in b"ABCDEFGHIJ;STOP:;" {
+        if let Some(res) = stream.push(byte) {
+            results.push(res).unwrap();
+        }
+    }
+    let expected = [
+        Err(ParsingError::NoSepparator { offset: 0, expected: ';' }),
+        Ok(Message::Stop),
+    ];
+    assert_eq(results.as_slice(), expected.as_slice());
+    assert_eq(stream.pending(), 0);
+    println!("PASSED");
+}
+
+#[named]
+fn hello_test() {
+    println!("{}", function_name!());
+    let car = Hello::local(Vehicle::Car);
+    assert_eq(car.protocol, PROTOCOL_VERSION);
+    assert_eq(car.compatibility(), Compatibility::Full);
+
+    let mut data: String<64> = String::new();
+    write!(&mut data, "{}", Message::Hello(car.clone())).unwrap();
+    assert_eq(parse(data.as_bytes()), Ok(Message::Hello(car.clone())));
+
+    let old = Hello {
+        protocol: 1,
+        firmware: String::try_from("0.9.0").unwrap(),
+        vehicle: Vehicle::Car,
+        messages: Capabilities::NONE
+            .with(MessageKind::LeftSpeed)
+            .with(MessageKind::RightSpeed)
+            .with(MessageKind::Stop),
+    };
+    assert_eq(parse(b"HELLO:1,0.9.0,CAR,7;"), Ok(Message::Hello(old.clone())));
+    // a car that can't be stopped safely isn't driven
+    assert_eq(old.compatibility(), Compatibility::Incompatible);
+    let old = Hello { messages: Capabilities::DRIVE, ..old };
+    assert_eq(old.compatibility(), Compatibility::Degraded);
+    assert_eq(old.messages.common(Capabilities::ALL), Capabilities::DRIVE);
+    assert_eq(old.messages.supports(MessageKind::Ping), false);
+    // a car that is switched away from is told to stop
+    assert_eq(old.messages.supports(MessageKind::Release), true);
+    let newer = Hello { protocol: PROTOCOL_VERSION + 1, ..car.clone() };
+    assert_eq(newer.compatibility(), Compatibility::Degraded);
+    let ancient = Hello { protocol: 0, ..car };
+    assert_eq(ancient.compatibility(), Compatibility::Incompatible);
+
+    assert_eq(Message::Pair([1; 32]).kind(), MessageKind::Pair);
+    assert_eq(Capabilities::ALL.supports(MessageKind::Hello), true);
+    assert_eq(Capabilities::ALL.contains(Capabilities::DRIVE), true);
+    for bad in [
+        b"HELLO:1,0.9.0,CAR;".as_slice(),
+        b"HELLO:1,0.9.0,CAR,ffff,1;",
+        b"HELLO:1,0.9.0,TRUCK,ffff;",
+        b"HELLO:x,0.9.0,CAR,ffff;",
+        b"HELLO:1,0.9.0,CAR,fffffffff;",
+    ] {
+        let res = parse(bad);
+        assert_eq(matches!(res, Err(ParsingError::ValueCanNotBeParsed { offset: 6, .. })), true);
+    }
+    println!("PASSED");
+}
+
+#[named]
+fn parse_param_test() {
+    println!("{}", function_name!());
+    assert_eq(parse(b"PGET:3;"), Ok(Message::ParamGet(3)));
+    assert_eq(parse(b"PLIST:;"), Ok(Message::ParamList));
+    assert_eq(parse(b"PSET:1,true;"), Ok(Message::ParamSet(1, ParamValue::Bool(true))));
+    assert_eq(parse(b"PSET:1,-7;"), Ok(Message::ParamSet(1, ParamValue::Int(-7))));
+    assert_eq(parse(b"PSET:1,0.25;"), Ok(Message::ParamSet(1, ParamValue::Float(0.25))));
+    let nan = ParsingError::OutOfRange { offset: 5, token: Token::new(b"1,NaN") };
+    assert_eq(parse(b"PSET:1,NaN;"), Err(nan));
+    for bad in [b"PSET:1;".as_slice(), b"PSET:300,1;", b"PSET:1,fast;", b"PGET:x;"] {
+        let res = parse(bad);
+        assert_eq(matches!(res, Err(ParsingError::ValueCanNotBeParsed { offset: 5, .. })), true);
+    }
+
+    let info = ParamInfo {
+        id: 4,
+        name: String::try_from("wifi_channel").unwrap(),
+        kind: ParamType::Int,
+        value: ParamValue::Int(6),
+        min: 1.0,
+        max: 13.0,
+        default: ParamValue::Int(3),
+        flags: ParamFlags::PERSIST.with(ParamFlags::REBOOT),
+    };
+    let mut data: String<64> = String::new();
+    write!(&mut data, "{}", Message::Param(info.clone())).unwrap();
+    assert_eq(data.as_str(), "PARAM:4,wifi_channel,INT,6,1,13,3,3;");
+    assert_eq(parse(data.as_bytes()), Ok(Message::Param(info)));
+    for message in [Message::ParamSet(2, ParamValue::Float(-1.5)), Message::ParamGet(0)] {
+        data.clear();
+        write!(&mut data, "{}", message).unwrap();
+        assert_eq(parse(data.as_bytes()), Ok(message));
+    }
+    println!("PASSED");
+}
+
+static TEST_PARAMS: [ParamDef; 3] = [
+    ParamDef {
+        id: 0,
+        name: "timeout_ms",
+        kind: ParamType::Int,
+        min: 100.0,
+        max: 5000.0,
+        default: ParamValue::Int(1000),
+        flags: ParamFlags::PERSIST,
+    },
+    ParamDef {
+        id: 1,
+        name: "expo",
+        kind: ParamType::Float,
+        min: 0.0,
+        max: 1.0,
+        default: ParamValue::Float(0.0),
+        flags: ParamFlags::NONE,
+    },
+    ParamDef {
+        id: 7,
+        name: "lights",
+        kind: ParamType::Bool,
+        min: 0.0,
+        max: 1.0,
+        default: ParamValue::Bool(false),
+        flags: ParamFlags::PERSIST,
+    },
+];
+
+#[named]
+fn param_table_test() {
+    println!("{}", function_name!());
+    let mut table = ParamTable::new(&TEST_PARAMS);
+    assert_eq(table.get(0), Some(ParamValue::Int(1000)));
+    assert_eq(table.get(2), None);
+    assert_eq(table.ids().count(), 3);
+
+    // whole numbers convert both ways, the range is kept
+    assert_eq(table.set(0, ParamValue::Float(250.0)), Ok(ParamValue::Int(250)));
+    assert_eq(table.set(0, ParamValue::Float(250.5)), Err(ParamError::WrongType));
+    assert_eq(table.set(0, ParamValue::Int(50)), Err(ParamError::OutOfRange));
+    assert_eq(table.set(1, ParamValue::Int(1)), Ok(ParamValue::Float(1.0)));
+    assert_eq(table.set(7, ParamValue::Int(1)), Err(ParamError::WrongType));
+    assert_eq(table.set(7, ParamValue::Bool(true)), Ok(ParamValue::Bool(true)));
+    assert_eq(table.set(2, ParamValue::Int(1)), Err(ParamError::Unknown));
+    assert_eq(table.int(0), 250);
+    assert_eq(table.float(1), 1.0);
+    assert_eq(table.info(0).map(|info| info.value), Some(ParamValue::Int(250)));
+
+    // only persisted parameters come back after a reboot
+    let bytes = table.to_bytes();
+    let mut restarted = ParamTable::new(&TEST_PARAMS);
+    assert_eq(restarted.apply_bytes(&bytes), true);
+    assert_eq(restarted.get(0), Some(ParamValue::Int(250)));
+    assert_eq(restarted.get(1), Some(ParamValue::Float(0.0)));
+    assert_eq(restarted.get(7), Some(ParamValue::Bool(true)));
+
+    let mut torn = bytes;
+    torn[7] ^= 1;
+    let mut restarted = ParamTable::new(&TEST_PARAMS);
+    assert_eq(restarted.apply_bytes(&torn), false);
+    assert_eq(restarted.apply_bytes(&[0xff; ParamTable::LEN]), false);
+    assert_eq(restarted.get(0), Some(ParamValue::Int(1000)));
+    println!("PASSED");
+}
+
+#[named]
+fn parse_link_test() {
+    println!("{}", function_name!());
+    assert_eq(parse(b"RSSI:-67;"), Ok(Message::Rssi(-67)));
+    assert_eq(parse(b"LOSS:12;"), Ok(Message::Loss(12)));
+    let too_low = ParsingError::ValueCanNotBeParsed { offset: 5, token: Token::new(b"-200") };
+    assert_eq(parse(b"RSSI:-200;"), Err(too_low));
+
+    let mut data: String<32> = String::new();
+    for message in [Message::Ping(4000000000), Message::Pong(17)] {
+        data.clear();
+        write!(&mut data, "{}", message).unwrap();
+        assert_eq(parse(data.as_bytes()), Ok(message));
+    }
+    assert_eq(data.as_str(), "PONG:17;");
+    println!("PASSED");
+}
+
+#[named]
+fn parse_crash_test() {
+    println!("{}", function_name!());
+    // the location has colons of its own
+    let record = "src/bin/rc_car.rs:42:9: called `Option::unwrap()` on a `None` value";
+    let crash = Message::Crash(String::try_from(record).unwrap());
+    let mut data: String<128> = String::new();
+    write!(&mut data, "{}", crash).unwrap();
+    assert_eq(parse(data.as_bytes()), Ok(crash));
+
+    data.clear();
+    data.push_str("CRASH:").unwrap();
+    for _ in 0..=CRASH_LEN {
+        data.push('x').unwrap();
+    }
+    data.push(';').unwrap();
+    let token = Token::new(&[b'x'; CRASH_LEN + 1]);
+    let too_long = ParsingError::ValueCanNotBeParsed { offset: 6, token };
+    assert_eq(parse(data.as_bytes()), Err(too_long));
+    println!("PASSED");
+}
+
+#[named]
+fn parse_pair_test() {
+    println!("{}", function_name!());
+    let mut key = [0; 32];
+    for (i, byte) in key.iter_mut().enumerate() {
+        *byte = i as u8 * 7;
+    }
+    let mut data: String<80> = String::new();
+    write!(&mut data, "{}", Message::Pair(key)).unwrap();
+    assert_eq(parse(data.as_bytes()), Ok(Message::Pair(key)));
+    // the old nonce
+    let res = parse(b"PAIR:4000000000;");
+    assert_eq(matches!(res, Err(ParsingError::ValueCanNotBeParsed { .. })), true);
+    println!("PASSED");
+}
+
+#[named]
+fn pairing_test() {
+    println!("{}", function_name!());
+    let link = LoopbackLink::new();
+    let (mut remote, mut car) = link.endpoints(REMOTE, CAR);
+
+    let (car_side, remote_side) = block_on(join(
+        pair_car(&mut car, [7; 32]),
+        pair_remote(&mut remote, &[0xff; 6], [42; 32]),
+    ));
+    let (car_side, remote_side) = (car_side.unwrap(), remote_side.unwrap());
+
+    assert_eq(car_side.peer, REMOTE);
+    assert_eq(remote_side.peer, CAR);
+    assert_eq(car_side.lmk, remote_side.lmk);
+    assert_eq(car_side.auth_key, remote_side.auth_key);
+    // the radio's key and the signing key are unrelated
+    assert_eq(car_side.auth_key[..16] == car_side.lmk, false);
+
+    // a fresh pairing gives a fresh key
+    let (again, _) = block_on(join(
+        pair_car(&mut car, [8; 32]),
+        pair_remote(&mut remote, &CAR, [42; 32]),
+    ));
+    assert_eq(again.unwrap().lmk == car_side.lmk, false);
+
+    // a low order point as public key would make the key public
+    let secret = StaticSecret::from([7; 32]);
+    assert_eq(derive_keys(&secret, &[0; 32], &REMOTE, &CAR), None);
+    let remote_key = PublicKey::from(&StaticSecret::from([42; 32])).to_bytes();
+    let keys = derive_keys(&secret, &remote_key, &REMOTE, &CAR).unwrap();
+    assert_eq(Binding::new(REMOTE, keys), car_side);
+    println!("PASSED");
+}
+
+#[named]
+fn config_test() {
+    println!("{}", function_name!());
+    let link = LoopbackLink::new();
+    let (mut remote, mut car) = link.endpoints(REMOTE, CAR);
+    let (binding, _) = block_on(join(
+        pair_car(&mut car, [1; 32]),
+        pair_remote(&mut remote, &CAR, [2; 32]),
+    ));
+
+    let mut config = Config {
+        boots: 7,
+        ..Config::default()
+    };
+    config.bind(binding.unwrap());
+    let bytes = config.to_bytes();
+    assert_eq(Config::from_bytes(&bytes), Some(config));
+    assert_eq(Config::from_bytes(&Config::default().to_bytes()), Some(Config::default()));
+
+    // erased flash
+    assert_eq(Config::from_bytes(&[0xff; Config::LEN]), None);
+    let mut corrupted = bytes;
+    corrupted[10] ^= 1;
+    assert_eq(Config::from_bytes(&corrupted), None);
+    println!("PASSED");
+}
+
+#[named]
+fn config_bindings_test() {
+    println!("{}", function_name!());
+    let binding = |last: u8, key: u8| Binding {
+        peer: [0x02, 0, 0, 0, 0, last],
+        lmk: [key; 16],
+        auth_key: [key; 32],
+        epoch: last as u32,
+    };
+    let mut config = Config::default();
+    for last in 1..=MAX_BINDINGS as u8 {
+        config.bind(binding(last, 0));
+    }
+    // pairing again replaces the key
+    config.bind(binding(2, 1));
+    assert_eq(config.bindings.len(), MAX_BINDINGS);
+    assert_eq(config.bindings.last(), Some(&binding(2, 1)));
+
+    // the oldest goes
+    config.bind(binding(9, 0));
+    assert_eq(config.bindings.len(), MAX_BINDINGS);
+    assert_eq(config.bindings.first(), Some(&binding(3, 0)));
+    assert_eq(Config::from_bytes(&config.to_bytes()), Some(config));
+    println!("PASSED");
+}
+
+#[named]
+fn supervisor_test() {
+    println!("{}", function_name!());
+    let at = Instant::from_millis;
+    let control = Heartbeat::new("control", Duration::from_millis(200));
+    let radio = Heartbeat::new("radio", Duration::from_secs(2));
+    let tasks = [&control, &radio];
+    let supervisor = Supervisor::new(&tasks, at(1000));
+
+    // not checked in yet, timed from the start
+    assert_eq(supervisor.check(at(1200)).is_ok(), true);
+    assert_eq(supervisor.check(at(1201)).map_err(Heartbeat::name), Err("control"));
+
+    control.beat_at(at(1150));
+    radio.beat_at(at(1150));
+    assert_eq(supervisor.check(at(1350)).is_ok(), true);
+    control.beat_at(at(3000));
+    assert_eq(supervisor.check(at(3151)).map_err(Heartbeat::name), Err("radio"));
+    println!("PASSED");
+}
+
+#[named]
+fn press_test() {
+    println!("{}", function_name!());
+    let mut button = PressDetector::new(Duration::from_secs(1));
+    let at = Instant::from_millis;
+    // (time in ms, pressed, expected)
+    let script = [
+        (0, false, None),
+        (20, true, None),
+        (40, true, None),
+        (100, false, Some(Press::Short)),
+        // bounce
+        (120, true, None),
+        (130, false, None),
+        (200, true, None),
+        (1100, true, None),
+        (1200, true, Some(Press::Long)),
+        (1500, true, None),
+        (1600, false, None),
+    ];
+    for (ms, pressed, expected) in script {
+        assert_eq(button.update(pressed, at(ms)), expected);
+    }
+    println!("PASSED");
+}
+
+#[named]
+fn parse_owner_test() {
+    println!("{}", function_name!());
+    let owner = Message::Owner(Some([0x54, 0x32, 0x04, 0x32, 0xf2, 0xb8]));
+    let mut data: String<32> = String::new();
+    write!(&mut data, "{}", owner).unwrap();
+    assert_eq(data.as_str(), "OWNER:54320432f2b8;");
+    assert_eq(parse(data.as_bytes()), Ok(owner));
+
+    assert_eq(parse(b"OWNER:;"), Ok(Message::Owner(None)));
+    assert_eq(parse(b"RELEASE:;"), Ok(Message::Release));
+    let short = ParsingError::ValueCanNotBeParsed { offset: 6, token: Token::new(b"5432") };
+    assert_eq(parse(b"OWNER:5432;"), Err(short));
+    println!("PASSED");
+}
+
+#[named]
+fn ownership_test() {
+    println!("{}", function_name!());
+    let mut ownership = Ownership::new(None);
+    // first come
+    assert_eq(ownership.accept(&REMOTE), true);
+    assert_eq(ownership.accept(&CAR), false);
+    assert_eq(ownership.rejected(), 1);
+    assert_eq(ownership.owner(), Some(REMOTE));
+
+    ownership.release();
+    assert_eq(ownership.owner(), None);
+    assert_eq(ownership.accept(&CAR), true);
+    assert_eq(ownership.accept(&REMOTE), false);
+    assert_eq(ownership.rejected(), 2);
+    println!("PASSED");
+}
+
+#[named]
+fn bound_ownership_test() {
+    println!("{}", function_name!());
+    let mut ownership = Ownership::new(Some(REMOTE));
+    assert_eq(ownership.owner(), Some(REMOTE));
+    assert_eq(ownership.accept(&CAR), false);
+
+    // the bound remote can't be replaced
+    ownership.release();
+    assert_eq(ownership.accept(&CAR), false);
+    assert_eq(ownership.accept(&REMOTE), true);
+    assert_eq(ownership.rejected(), 2);
+    println!("PASSED");
+}
+
+fn signed(auth: &mut Authenticator, payload: &[u8]) -> Vec<u8, 64> {
+    let mut trailer: String<TRAILER_LEN> = String::new();
+    write!(&mut trailer, "{}", auth.sign(payload)).unwrap();
+    let mut frame = Vec::from_slice(payload).unwrap();
+    frame.extend_from_slice(trailer.as_bytes()).unwrap();
+    frame
+}
+
+#[named]
+fn auth_test() {
+    println!("{}", function_name!());
+    let mut remote = Authenticator::new(b"key", 5 << 32, None);
+    let mut car = Authenticator::new(b"key", 0, None);
+    let first = signed(&mut remote, b"LSPEED:10;");
+    let second = signed(&mut remote, b"STOP:;");
+
+    assert_eq(first.len(), 10 + TRAILER_LEN);
+    assert_eq(car.verify(&first), Ok(b"LSPEED:10;".as_slice()));
+    assert_eq(car.verify(b"STOP:;"), Err(ParsingError::NotAuthenticated));
+    // wrong key
+    assert_eq(Authenticator::new(b"other", 0, None).verify(&second), Err(ParsingError::BadTag));
+    assert_eq(car.verify(&second), Ok(b"STOP:;".as_slice()));
+    println!("PASSED");
+}
+
+#[named]
+fn auth_tampering_test() {
+    println!("{}", function_name!());
+    let mut remote = Authenticator::new(b"key", 0, None);
+    let mut car = Authenticator::new(b"key", 0, None);
+    let frame = signed(&mut remote, b"LSPEED:10;");
+
+    let mut payload = frame.clone();
+    payload[8] = b'9';
+    assert_eq(car.verify(&payload), Err(ParsingError::BadTag));
+
+    let mut tag = frame.clone();
+    let last_digit = tag.len() - 2;
+    tag[last_digit] ^= 1;
+    assert_eq(car.verify(&tag), Err(ParsingError::BadTag));
+
+    let mut seq = frame.clone();
+    seq[TRAILER_LEN - 10] = b'1';
+    assert_eq(car.verify(&seq), Err(ParsingError::BadTag));
+
+    assert_eq(car.verify(&frame), Ok(b"LSPEED:10;".as_slice()));
+    println!("PASSED");
+}
+
+#[named]
+fn auth_replay_test() {
+    println!("{}", function_name!());
+    let mut remote = Authenticator::new(b"key", 0, None);
+    let mut car = Authenticator::new(b"key", 0, None);
+    let first = signed(&mut remote, b"LSPEED:10;");
+    let second = signed(&mut remote, b"LSPEED:20;");
+
+    assert_eq(car.verify(&second), Ok(b"LSPEED:20;".as_slice()));
+    assert_eq(car.verify(&second), Err(ParsingError::Replayed));
+    // older than the last accepted one
+    assert_eq(car.verify(&first), Err(ParsingError::Replayed));
+
+    // a restarted remote continues above everything sent before
+    let mut restarted = Authenticator::new(b"key", 1 << 32, None);
+    let third = signed(&mut restarted, b"STOP:;");
+    assert_eq(car.verify(&third), Ok(b"STOP:;".as_slice()));
+    println!("PASSED");
+}
+
+#[named]
+fn auth_epoch_test() {
+    println!("{}", function_name!());
+    let mut remote = Authenticator::new(b"key", 5 << 32, None);
+    let recorded = signed(&mut remote, b"LSPEED:10;");
+    // the car restarted, it stored epoch 5 of the remote
+    let mut car = Authenticator::new(b"key", 0, Some(5));
+    assert_eq(car.verify(&recorded), Err(ParsingError::Replayed));
+    assert_eq(car.verify(&signed(&mut remote, b"STOP:;")), Err(ParsingError::Replayed));
+    assert_eq(car.heard(), false);
+
+    remote.next_epoch();
+    let moved_on = signed(&mut remote, b"STOP:;");
+    assert_eq(car.verify(&moved_on), Ok(b"STOP:;".as_slice()));
+    assert_eq(car.peer_epoch(), Some(6));
+    assert_eq(car.verify(&recorded), Err(ParsingError::Replayed));
+    println!("PASSED");
+}
+
+// what the hook was told last
+static NEW_EPOCH: AtomicU32 = AtomicU32::new(0);
+
+#[named]
+fn signed_transport_epoch_test() {
+    println!("{}", function_name!());
+    let link = LoopbackLink::new();
+    let (remote, car) = link.endpoints(REMOTE, CAR);
+    let mut remote = SignedTransport::new(remote);
+    remote.add_peer(CAR, Authenticator::new(b"key", 3 << 32, Some(7))).unwrap();
+    // restarted at epoch 8, remembers the remote's epoch 3
+    let mut car = SignedTransport::new(car);
+    car.add_peer(REMOTE, Authenticator::new(b"key", 8 << 32, Some(3))).unwrap();
+    car.on_new_epoch(|_, epoch| NEW_EPOCH.store(epoch, Ordering::Relaxed));
+
+    block_on(async {
+        remote.send(&CAR, b"STOP:;").await.unwrap();
+        // refused, the car nudges the remote into epoch 4 and waits for the next frame
+        let _ = select(car.receive(), Timer::after(Duration::from_millis(10))).await;
+        assert_eq(NEW_EPOCH.load(Ordering::Relaxed), 0);
+        // the nudge is taken by the remote, not handed out
+        let _ = select(remote.receive(), Timer::after(Duration::from_millis(10))).await;
+
+        remote.send(&CAR, b"ARM:;").await.unwrap();
+        let frame = car.receive().await.unwrap();
+        assert_eq(frame.data.as_slice(), b"ARM:;".as_slice());
+        assert_eq(NEW_EPOCH.load(Ordering::Relaxed), 4);
+    });
+    println!("PASSED");
+}
+
+#[named]
+fn link_metrics_test() {
+    println!("{}", function_name!());
+    let mut remote = Authenticator::new(b"key", 0, None);
+    let mut car = Authenticator::new(b"key", 0, None);
+    let frames = [(); 6].map(|_| signed(&mut remote, b"LSPEED:10;"));
+
+    // the 2nd and the 4th to 5th never arrive
+    for i in [0, 2, 5] {
+        assert_eq(car.verify(&frames[i]), Ok(b"LSPEED:10;".as_slice()));
+    }
+    assert_eq(car.lost(), 3);
+
+    // a restarted remote is no loss
+    let mut restarted = Authenticator::new(b"key", 1 << 32, None);
+    assert_eq(car.verify(&signed(&mut restarted, b"STOP:;")), Ok(b"STOP:;".as_slice()));
+    assert_eq(car.lost(), 3);
+
+    let earlier = LinkMetrics { received: 10, lost: 1, ..LinkMetrics::default() };
+    let now = LinkMetrics { received: 19, lost: 2, ..LinkMetrics::default() };
+    assert_eq(now.loss_since(&earlier), Some(10));
+    assert_eq(now.loss_since(&now), None);
+
+    // the signed link reports what its authenticators have missed
+    let link = LoopbackLink::new();
+    let (mut remote, car) = link.endpoints(REMOTE, CAR);
+    let mut car = SignedTransport::new(car);
+    car.add_peer(REMOTE, Authenticator::new(b"key", 0, None)).unwrap();
+    let mut signer = Authenticator::new(b"key", 0, None);
+    let frames = [(); 3].map(|_| signed(&mut signer, b"STOP:;"));
+    block_on(async {
+        for i in [0, 2] {
+            remote.send(&CAR, &frames[i]).await.unwrap();
+            car.receive().await.unwrap();
+        }
+    });
+    assert_eq(car.metrics().received, 2);
+    assert_eq(car.metrics().lost, 1);
+    println!("PASSED");
+}
+
+#[named]
+fn latency_test() {
+    println!("{}", function_name!());
+    let mut latency = LatencyStats::default();
+    assert_eq(latency.summary(), None);
+
+    for ms in [4, 8, 6, 10] {
+        latency.record(Duration::from_millis(ms));
+    }
+    let summary = latency.summary().unwrap();
+    assert_eq(summary.samples, 4);
+    assert_eq(summary.min, Duration::from_millis(4));
+    assert_eq(summary.avg, Duration::from_millis(7));
+    assert_eq(summary.max, Duration::from_millis(10));
+    // |8-4| + |6-8| + |10-6| over 3
+    assert_eq(summary.jitter, Duration::from_millis(10) / 3);
+
+    latency.reset();
+    latency.record(Duration::from_millis(5));
+    assert_eq(latency.summary().unwrap().jitter, Duration::from_ticks(0));
+    println!("PASSED");
+}
+
+#[named]
+fn signed_transport_test() {
+    println!("{}", function_name!());
+    let link = LoopbackLink::new();
+    let (remote, car) = link.endpoints(REMOTE, CAR);
+    let mut remote = SignedTransport::new(remote);
+    remote.add_peer(CAR, Authenticator::new(b"car key", 0, None)).unwrap();
+    remote.add_peer(OTHER_CAR, Authenticator::new(b"other key", 0, None)).unwrap();
+    let mut car = SignedTransport::new(car);
+    car.add_peer(REMOTE, Authenticator::new(b"car key", 0, None)).unwrap();
+
+    block_on(async {
+        remote.send(&CAR, b"BATT:5;").await.unwrap();
+        let frame = car.receive().await.unwrap();
+        assert_eq(frame.data.as_slice(), b"BATT:5;".as_slice());
+
+        // signed with the other car's key, loopback delivers it anyway
+        remote.send(&OTHER_CAR, b"STOP:;").await.unwrap();
+        remote.send(&CAR, b"STOP:;").await.unwrap();
+        let frame = car.receive().await.unwrap();
+        assert_eq(frame.data.as_slice(), b"STOP:;".as_slice());
+        assert_eq(remote.send(&[0xff; 6], b"STOP:;").await, Err(TransportError::Peer));
+    });
+    println!("PASSED");
+}
+
+#[cfg(feature = "web")]
+#[named]
+fn websocket_test() {
+    println!("{}", function_name!());
+    // RFC 6455 examples
+    assert_eq(&accept_key(b"dGhlIHNhbXBsZSBub25jZQ=="), b"s3pPLMBiTxaQ9kYGzzhZRbK+xOo=");
+
+    // masked "Hello"
+    let mut frame = [0x81, 0x85, 0x37, 0xfa, 0x21, 0x3d, 0x7f, 0x9f, 0x4d, 0x51, 0x58, 0xff];
+    assert_eq(decode(&mut frame[..10]), Ok(None));
+    let header = decode(&mut frame).unwrap().unwrap();
+    assert_eq(header.opcode, Opcode::Text);
+    assert_eq(header.len, 11);
+    assert_eq(&frame[header.payload], b"Hello".as_slice());
+
+    let mut out = [0u8; 256];
+    let len = encode(Opcode::Text, b"Hello", &mut out).unwrap();
+    assert_eq(&out[..len], [0x81, 0x05, 0x48, 0x65, 0x6c, 0x6c, 0x6f].as_slice());
+    // extended 16 bit length
+    let len = encode(Opcode::Text, &[b'a'; 200], &mut out).unwrap();
+    assert_eq(len, 204);
+    assert_eq(&out[..4], [0x81, 126, 0, 200].as_slice());
+    println!("PASSED");
+}
+
+#[cfg(feature = "web")]
+#[named]
+fn http_request_test() {
+    println!("{}", function_name!());
+    let request = parse_request(b"GET /ws HTTP/1.1\r\nHost: x\r\nSec-WebSocket-Key: abc==\r\n\r\nrest")
+        .unwrap()
+        .unwrap();
+    assert_eq(request.method, "GET");
+    assert_eq(request.path, "/ws");
+    assert_eq(request.websocket_key, Some("abc=="));
+    assert_eq(request.len, 55);
 
+    assert_eq(parse_request(b"GET / HTTP/1.1\r\n"), Ok(None));
     println!("PASSED");
 }
 
@@ -83,6 +1326,55 @@ pub fn run_tests() {
     parse_value_error_test();
     parse_not_a_comand_error_test();
     parse_sepparator_error_test();
+    parse_battery_test();
+    parse_diagnostics_test();
+    parse_validation_test();
+    parse_whitespace_test();
+    parse_non_ascii_test();
+    tokenizer_test();
+    battery_hysteresis_test();
+    obstacle_approach_test();
+    obstacle_release_test();
+    obstacle_dropout_test();
+    obstacle_reverse_test();
+    arbiter_test();
+    emergency_stop_test();
+    emergency_stop_latched_test();
+    arming_test();
+    parse_safety_test();
+    send_policy_test();
+    error_test();
+    loopback_transport_test();
+    loopback_drives_arbiter_test();
+    parse_all_test();
+    inbox_test();
+    stream_parser_test();
+    parse_pair_test();
+    parse_link_test();
+    hello_test();
+    parse_param_test();
+    param_table_test();
+    parse_crash_test();
+    pairing_test();
+    config_test();
+    auth_test();
+    auth_tampering_test();
+    auth_replay_test();
+    auth_epoch_test();
+    signed_transport_epoch_test();
+    signed_transport_test();
+    link_metrics_test();
+    latency_test();
+    config_bindings_test();
+    press_test();
+    supervisor_test();
+    parse_owner_test();
+    ownership_test();
+    bound_ownership_test();
+    #[cfg(feature = "web")]
+    websocket_test();
+    #[cfg(feature = "web")]
+    http_request_test();
     println!("All tests passed")
 }
 
@@ -0,0 +1,151 @@
+use embassy_futures::{
+    join::join,
+    select::{Either, select},
+};
+use heapless::Vec;
+use log::{info, warn};
+use trouble_host::prelude::*;
+
+use crate::transport::{MAX_FRAME, PeerId, Transport};
+
+// what the car side of the link sees as the sender of BLE commands
+pub const BLE_PEER: PeerId = [0xb1, 0xe0, 0x00, 0x00, 0x00, 0x01];
+
+pub const NAME: &str = "robo_remote";
+
+const CONNECTIONS_MAX: usize = 1;
+// signal + att
+const L2CAP_CHANNELS_MAX: usize = 2;
+const L2CAP_MTU: usize = 255;
+
+// any frame the car sends fits, a crash report is the longest
+const VALUE_LEN: usize = MAX_FRAME;
+// a notification adds its opcode and handle
+const _: () = assert!(VALUE_LEN + 3 <= L2CAP_MTU);
+
+#[gatt_server]
+struct Server {
+    control: ControlService,
+}
+
+// same text frames as on ESP-NOW, e.g. "LSPEED:50;"
+#[gatt_service(uuid = "5a1e0001-7c6d-4b6e-9f0a-52c0b0b0ca70")]
+struct ControlService {
+    #[characteristic(uuid = "5a1e0002-7c6d-4b6e-9f0a-52c0b0b0ca70", write, write_without_response)]
+    command: Vec<u8, VALUE_LEN>,
+    #[characteristic(uuid = "5a1e0003-7c6d-4b6e-9f0a-52c0b0b0ca70", read, notify)]
+    telemetry: Vec<u8, VALUE_LEN>,
+}
+
+// Runs the GATT control service, written commands are sent into `link` and frames
+// received from it are notified as telemetry
+pub async fn run<C, T>(controller: C, address: [u8; 6], mut link: T)
+where
+    C: Controller,
+    T: Transport,
+{
+    let mut resources: HostResources<CONNECTIONS_MAX, L2CAP_CHANNELS_MAX, L2CAP_MTU> =
+        HostResources::new();
+    // a static random address needs the two top bits set
+    let mut address = address;
+    address[5] |= 0xc0;
+    let stack = trouble_host::new(controller, &mut resources)
+        .set_random_address(Address::random(address));
+    let Host {
+        mut peripheral,
+        mut runner,
+        ..
+    } = stack.build();
+
+    let server = Server::new_with_config(GapConfig::Peripheral(PeripheralConfig {
+        name: NAME,
+        appearance: &appearance::UNKNOWN,
+    }))
+    .unwrap();
+
+    let _ = join(
+        async {
+            loop {
+                if let Err(err) = runner.run().await {
+                    warn!("ble runner: {:?}", err);
+                }
+            }
+        },
+        async {
+            loop {
+                match advertise(&mut peripheral, &server).await {
+                    Ok(conn) => serve(&server, &conn, &mut link).await,
+                    Err(err) => warn!("ble advertising: {:?}", err),
+                }
+            }
+        },
+    )
+    .await;
+}
+
+async fn advertise<'a, 'b, C: Controller>(
+    peripheral: &mut Peripheral<'a, C>,
+    server: &'b Server<'_>,
+) -> Result<GattConnection<'a, 'b>, BleHostError<C::Error>> {
+    let mut adv_data = [0; 31];
+    AdStructure::encode_slice(
+        &[
+            AdStructure::Flags(LE_GENERAL_DISCOVERABLE | BR_EDR_NOT_SUPPORTED),
+            AdStructure::CompleteLocalName(NAME.as_bytes()),
+        ],
+        &mut adv_data[..],
+    )?;
+    let advertiser = peripheral
+        .advertise(
+            &Default::default(),
+            Advertisement::ConnectableScannableUndirected {
+                adv_data: &adv_data[..],
+                scan_data: &[],
+            },
+        )
+        .await?;
+    info!("ble advertising");
+    let conn = advertiser.accept().await?.with_attribute_server(server)?;
+    info!("ble connected");
+    Ok(conn)
+}
+
+async fn serve<T: Transport>(server: &Server<'_>, conn: &GattConnection<'_, '_>, link: &mut T) {
+    let command = server.control.command;
+    let telemetry = server.control.telemetry;
+    loop {
+        match select(conn.next(), link.receive()).await {
+            Either::First(GattConnectionEvent::Disconnected { reason }) => {
+                info!("ble disconnected: {:?}", reason);
+                return;
+            }
+            Either::First(GattConnectionEvent::Gatt { event: Ok(event) }) => {
+                if let GattEvent::Write(write) = &event {
+                    if write.handle() == command.handle {
+                        if let Err(err) = link.send(&BLE_PEER, write.data()).await {
+                            warn!("ble command dropped: {:?}", err);
+                        }
+                    }
+                }
+                match event.accept() {
+                    Ok(reply) => reply.send().await,
+                    Err(err) => warn!("ble reply: {:?}", err),
+                }
+            }
+            Either::First(GattConnectionEvent::Gatt { event: Err(err) }) => {
+                warn!("ble event: {:?}", err);
+            }
+            Either::First(_) => (),
+            Either::Second(Ok(frame)) => {
+                let Ok(value) = Vec::from_slice(&frame.data) else {
+                    warn!("telemetry frame is too long for ble");
+                    continue;
+                };
+                if telemetry.notify(conn, &value).await.is_err() {
+                    return;
+                }
+            }
+            Either::Second(Err(err)) => warn!("ble link: {:?}", err),
+        }
+    }
+}
@@ -0,0 +1,316 @@
+#[cfg(feature = "web")]
+use embassy_net::{Config, Ipv4Cidr, Runner, Stack, StackResources, StaticConfigV4};
+use embassy_time::{Duration, Instant, with_deadline, with_timeout};
+use esp_hal::{
+    clock::CpuClock,
+    efuse::Efuse,
+    gpio::Input,
+    peripherals::{
+        self, ADC1, BT, GPIO0, GPIO1, GPIO2, GPIO3, GPIO4, GPIO5, GPIO6, GPIO7, GPIO9, MCPWM0,
+        TIMG1, UART1, WIFI,
+    },
+    rng::Rng,
+    timer::{systimer::SystemTimer, timg::TimerGroup},
+};
+use esp_println::println;
+use esp_storage::FlashStorage;
+use heapless::Vec;
+#[cfg(feature = "web")]
+use esp_wifi::wifi::{WifiController, WifiDevice};
+use esp_wifi::{
+    EspWifiController,
+    esp_now::{BROADCAST_ADDRESS, EspNow, PeerInfo},
+    init as wifi_init,
+};
+
+use crate::{
+    config::{self, MAX_BINDINGS},
+    error::Error,
+    mk_static, pairing,
+    protocol::auth::Authenticator,
+    transport::{PeerId, esp_now::EspNowTransport, signed::SignedTransport},
+};
+
+// long enough to walk over and hold the other device's button too; the first
+// PAIR heard within it is trusted, see `pairing::derive_keys`
+const PAIRING_TIMEOUT: Duration = Duration::from_secs(30);
+// BOOT is a strapping pin, held through a reset the chip stays in the ROM loader;
+// pairing is asked for by holding it for `PAIR_HOLD` soon after start-up instead
+const PAIR_WINDOW: Duration = Duration::from_secs(2);
+const PAIR_HOLD: Duration = Duration::from_secs(1);
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Role {
+    // accepts commands from whoever talks to it
+    Car,
+    // talks to a single peer, registered up front
+    Remote { peer: [u8; 6] },
+    // forwards received frames to another link
+    Bridge,
+}
+
+// What's left once the radio and the embassy time driver took TIMG0, RNG, RADIO_CLK,
+// WIFI and SYSTIMER. Only what the firmware wires up, add a field for another one.
+#[allow(non_snake_case)]
+pub struct Peripherals {
+    pub ADC1: ADC1,
+    pub BT: BT,
+    pub GPIO0: GPIO0,
+    pub GPIO1: GPIO1,
+    pub GPIO2: GPIO2,
+    pub GPIO3: GPIO3,
+    pub GPIO4: GPIO4,
+    pub GPIO5: GPIO5,
+    pub GPIO6: GPIO6,
+    pub GPIO7: GPIO7,
+    pub GPIO9: GPIO9,
+    pub MCPWM0: MCPWM0,
+    pub TIMG1: TIMG1,
+    pub UART1: UART1,
+}
+
+pub struct Board {
+    pub peripherals: Peripherals,
+    pub wifi_controller: &'static EspWifiController<'static>,
+    pub esp_now: EspNow<'static>,
+    // station MAC, what peers see as our address
+    pub address: PeerId,
+    // true random once the radio is up
+    pub rng: Rng,
+}
+
+// clocks, heap, logger, embassy time driver and the Wi-Fi controller
+fn bring_up() -> (Peripherals, &'static EspWifiController<'static>, Rng, WIFI) {
+    esp_println::logger::init_logger_from_env();
+    let config = esp_hal::Config::default().with_cpu_clock(CpuClock::max());
+    let peripherals::Peripherals {
+        TIMG0,
+        RNG,
+        RADIO_CLK,
+        WIFI,
+        SYSTIMER,
+        ADC1,
+        BT,
+        GPIO0,
+        GPIO1,
+        GPIO2,
+        GPIO3,
+        GPIO4,
+        GPIO5,
+        GPIO6,
+        GPIO7,
+        GPIO9,
+        MCPWM0,
+        TIMG1,
+        UART1,
+        ..
+    } = esp_hal::init(config);
+    let peripherals = Peripherals {
+        ADC1,
+        BT,
+        GPIO0,
+        GPIO1,
+        GPIO2,
+        GPIO3,
+        GPIO4,
+        GPIO5,
+        GPIO6,
+        GPIO7,
+        GPIO9,
+        MCPWM0,
+        TIMG1,
+        UART1,
+    };
+
+    esp_alloc::heap_allocator!(size: 72 * 1024);
+
+    let timg0 = TimerGroup::new(TIMG0);
+    let rng = Rng::new(RNG);
+
+    let wifi_controller = &*mk_static!(
+        EspWifiController<'static>,
+        wifi_init(timg0.timer0, rng, RADIO_CLK).unwrap()
+    );
+
+    let systimer = SystemTimer::new(SYSTIMER);
+    esp_hal_embassy::init(systimer.alarm0);
+
+    (peripherals, wifi_controller, rng, WIFI)
+}
+
+// board bring-up and ESP-NOW on the given channel
+pub fn init(channel: u8, role: Role) -> Board {
+    let (peripherals, wifi_controller, rng, wifi) = bring_up();
+
+    let esp_now = EspNow::new(wifi_controller, wifi).unwrap();
+    if let Ok(version) = esp_now.version() {
+        println!("esp-now version {}", version);
+    }
+    // peers on another channel never hear us, but the failsafe keeps the car safe
+    if let Err(err) = esp_now.set_channel(channel) {
+        println!("Setting channel {} failed: {:?}", channel, err);
+    }
+
+    if let Role::Remote { peer } = role {
+        let info = PeerInfo {
+            peer_address: peer,
+            lmk: None,
+            channel: None,
+            encrypt: false,
+        };
+        // the transport adds it again when sending
+        if !esp_now.peer_exists(&peer) && esp_now.add_peer(info).is_err() {
+            println!("Adding peer {:02x?} failed", peer);
+        }
+    }
+
+    Board {
+        peripherals,
+        wifi_controller,
+        esp_now,
+        address: Efuse::mac_address(),
+        rng,
+    }
+}
+
+// true when `button` (BOOT, active low) is pressed within `PAIR_WINDOW` after
+// start-up and held for `PAIR_HOLD`
+pub async fn pair_requested(button: &mut Input<'_>) -> bool {
+    let deadline = Instant::now() + PAIR_WINDOW;
+    while with_deadline(deadline, button.wait_for_low()).await.is_ok() {
+        if with_timeout(PAIR_HOLD, button.wait_for_high()).await.is_err() {
+            return true;
+        }
+    }
+    false
+}
+
+// Pairs first when asked to (see `pair_requested`), then encrypts and signs the
+// link to the stored peers and returns them. While unpaired frames are neither
+// encrypted nor signed.
+pub async fn bind_link<'d>(
+    mut transport: EspNowTransport<'d>,
+    role: Role,
+    pair: bool,
+    rng: &mut Rng,
+) -> (SignedTransport<EspNowTransport<'d>>, Vec<PeerId, MAX_BINDINGS>) {
+    let mut flash = FlashStorage::new();
+    let mut config = config::load(&mut flash);
+
+    if pair {
+        println!("Pairing");
+        // a fresh key pair for every pairing
+        let mut secret = [0; 32];
+        rng.read(&mut secret);
+        let pairing = async {
+            match role {
+                Role::Remote { .. } => {
+                    pairing::pair_remote(&mut transport, &BROADCAST_ADDRESS, secret).await
+                }
+                Role::Car | Role::Bridge => pairing::pair_car(&mut transport, secret).await,
+            }
+        };
+        match with_timeout(PAIRING_TIMEOUT, pairing).await {
+            Ok(Ok(binding)) => {
+                // a car follows a single remote
+                if !matches!(role, Role::Remote { .. }) {
+                    config.bindings.clear();
+                }
+                config.bind(binding);
+            }
+            Ok(Err(err)) => println!("Pairing failed: {:?}", err),
+            Err(_) => println!("Pairing timed out"),
+        }
+    }
+
+    let mut peers = Vec::new();
+    if config.bindings.is_empty() {
+        return (SignedTransport::new(transport), peers);
+    }
+
+    config.boots = config.boots.wrapping_add(1);
+    if let Err(err) = config::save(&mut flash, &config).map_err(|_| Error::Config) {
+        // a peer that remembers this epoch nudges us into the next one
+        println!("Config is not saved: {}", err);
+    }
+    let first_seq = (config.boots as u64) << 32;
+
+    for binding in &config.bindings {
+        match transport.bind(binding) {
+            Ok(()) => {
+                println!("Bound to {:02x?}", binding.peer);
+                // as many as there are bindings
+                let _ = peers.push(binding.peer);
+            }
+            Err(err) => println!("Binding to {:02x?} failed: {:?}", binding.peer, err),
+        }
+    }
+
+    let mut transport = SignedTransport::new(transport);
+    for binding in config.bindings.iter().filter(|binding| peers.contains(&binding.peer)) {
+        let auth = Authenticator::new(&binding.auth_key, first_seq, Some(binding.epoch));
+        // as many as there are bindings
+        let _ = transport.add_peer(binding.peer, auth);
+    }
+    transport.on_new_epoch(|peer, epoch| {
+        if let Err(err) = save_epoch(peer, epoch) {
+            println!("Epoch of {:02x?} is not saved: {}", peer, err);
+        }
+    });
+    (transport, peers)
+}
+
+// Stores a peer's boot epoch with its binding, frames from that boot are refused
+// after our next reset. Blocks on the flash.
+pub fn save_epoch(peer: PeerId, epoch: u32) -> Result<(), Error> {
+    let mut flash = FlashStorage::new();
+    let mut config = config::load(&mut flash);
+    let Some(binding) = config.bindings.iter_mut().find(|binding| binding.peer == peer) else {
+        return Ok(());
+    };
+    if binding.epoch >= epoch {
+        return Ok(());
+    }
+    binding.epoch = epoch;
+    config::save(&mut flash, &config).map_err(|_| Error::Config)
+}
+
+#[cfg(feature = "web")]
+pub struct AccessPointBoard {
+    // same as in `Board`
+    pub peripherals: Peripherals,
+    pub wifi_controller: &'static EspWifiController<'static>,
+    // has to be started, see `web::run_access_point`
+    pub controller: WifiController<'static>,
+    pub stack: Stack<'static>,
+    pub runner: Runner<'static, WifiDevice<'static>>,
+}
+
+// board bring-up and a network stack on the soft-AP interface, the station
+// gets the gateway address
+#[cfg(feature = "web")]
+pub fn init_access_point(gateway: core::net::Ipv4Addr) -> AccessPointBoard {
+    let (peripherals, wifi_controller, mut rng, wifi) = bring_up();
+    let seed = ((rng.random() as u64) << 32) | rng.random() as u64;
+
+    let (controller, interfaces) = esp_wifi::wifi::new(wifi_controller, wifi).unwrap();
+    let config = Config::ipv4_static(StaticConfigV4 {
+        address: Ipv4Cidr::new(gateway, 24),
+        gateway: Some(gateway),
+        dns_servers: Default::default(),
+    });
+    let (stack, runner) = embassy_net::new(
+        interfaces.ap,
+        config,
+        mk_static!(StackResources<4>, StackResources::<4>::new()),
+        seed,
+    );
+
+    AccessPointBoard {
+        peripherals,
+        wifi_controller,
+        controller,
+        stack,
+        runner,
+    }
+}
@@ -0,0 +1,136 @@
+use embedded_storage::{ReadStorage, Storage};
+use heapless::Vec;
+use log::warn;
+
+use crate::{
+    pairing::{AuthKey, LinkKeys, Lmk},
+    transport::PeerId,
+};
+
+// start of the nvs partition, nothing else on the board uses it
+pub const CONFIG_OFFSET: u32 = 0x9000;
+
+// a car keeps one, a remote one per car it drives
+pub const MAX_BINDINGS: usize = 4;
+
+const MAGIC: [u8; 4] = *b"RRCF";
+const VERSION: u8 = 5;
+// peer, lmk, auth key, epoch
+const BINDING_LEN: usize = 6 + 16 + 32 + 4;
+// magic, version, binding count, boots
+const HEADER_LEN: usize = 4 + 1 + 1 + 4;
+
+// a peer this device was paired with
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Binding {
+    pub peer: PeerId,
+    pub lmk: Lmk,
+    pub auth_key: AuthKey,
+    // the peer's boot epoch as last seen, 0 before any
+    pub epoch: u32,
+}
+
+impl Binding {
+    // freshly paired, no epoch seen yet
+    pub fn new(peer: PeerId, keys: LinkKeys) -> Self {
+        Self {
+            peer,
+            lmk: keys.lmk,
+            auth_key: keys.auth,
+            epoch: 0,
+        }
+    }
+}
+
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct Config {
+    pub bindings: Vec<Binding, MAX_BINDINGS>,
+    // counted while bound, starts a fresh range of frame sequence numbers
+    pub boots: u32,
+}
+
+impl Config {
+    // header, bindings, checksum
+    pub const LEN: usize = HEADER_LEN + MAX_BINDINGS * BINDING_LEN + 1;
+
+    // replaces an earlier pairing with the same peer, the oldest one goes when full
+    pub fn bind(&mut self, binding: Binding) {
+        self.bindings.retain(|bound| bound.peer != binding.peer);
+        if self.bindings.is_full() {
+            self.bindings.remove(0);
+        }
+        // there is room now
+        let _ = self.bindings.push(binding);
+    }
+
+    pub fn to_bytes(&self) -> [u8; Self::LEN] {
+        let mut bytes = [0; Self::LEN];
+        bytes[..4].copy_from_slice(&MAGIC);
+        bytes[4] = VERSION;
+        bytes[5] = self.bindings.len() as u8;
+        bytes[6..10].copy_from_slice(&self.boots.to_le_bytes());
+        for (i, binding) in self.bindings.iter().enumerate() {
+            let at = HEADER_LEN + i * BINDING_LEN;
+            bytes[at..at + 6].copy_from_slice(&binding.peer);
+            bytes[at + 6..at + 22].copy_from_slice(&binding.lmk);
+            bytes[at + 22..at + 54].copy_from_slice(&binding.auth_key);
+            bytes[at + 54..at + BINDING_LEN].copy_from_slice(&binding.epoch.to_le_bytes());
+        }
+        bytes[Self::LEN - 1] = checksum(&bytes[..Self::LEN - 1]);
+        bytes
+    }
+
+    // None for erased flash, another layout or a torn write
+    pub fn from_bytes(bytes: &[u8; Self::LEN]) -> Option<Self> {
+        let count = bytes[5] as usize;
+        if bytes[..4] != MAGIC
+            || bytes[4] != VERSION
+            || count > MAX_BINDINGS
+            || bytes[Self::LEN - 1] != checksum(&bytes[..Self::LEN - 1])
+        {
+            return None;
+        }
+
+        let mut config = Self::default();
+        let mut boots = [0; 4];
+        boots.copy_from_slice(&bytes[6..10]);
+        config.boots = u32::from_le_bytes(boots);
+        for i in 0..count {
+            let at = HEADER_LEN + i * BINDING_LEN;
+            let mut binding = Binding {
+                peer: [0; 6],
+                lmk: [0; 16],
+                auth_key: [0; 32],
+                epoch: 0,
+            };
+            let mut epoch = [0; 4];
+            binding.peer.copy_from_slice(&bytes[at..at + 6]);
+            binding.lmk.copy_from_slice(&bytes[at + 6..at + 22]);
+            binding.auth_key.copy_from_slice(&bytes[at + 22..at + 54]);
+            epoch.copy_from_slice(&bytes[at + 54..at + BINDING_LEN]);
+            binding.epoch = u32::from_le_bytes(epoch);
+            config.bindings.push(binding).ok()?;
+        }
+        Some(config)
+    }
+}
+
+pub fn checksum(bytes: &[u8]) -> u8 {
+    bytes
+        .iter()
+        .fold(0xa5, |sum: u8, byte| sum.rotate_left(1) ^ byte)
+}
+
+// defaults when nothing valid is stored
+pub fn load<S: ReadStorage>(storage: &mut S) -> Config {
+    let mut bytes = [0; Config::LEN];
+    if storage.read(CONFIG_OFFSET, &mut bytes).is_err() {
+        warn!("config can't be read");
+        return Config::default();
+    }
+    Config::from_bytes(&bytes).unwrap_or_default()
+}
+
+pub fn save<S: Storage>(storage: &mut S, config: &Config) -> Result<(), S::Error> {
+    storage.write(CONFIG_OFFSET, &config.to_bytes())
+}
@@ -0,0 +1,7 @@
+pub mod arbiter;
+pub mod arming;
+pub mod obstacle;
+pub mod owner;
+pub mod press;
+pub mod send_policy;
+pub mod supervisor;
@@ -0,0 +1,95 @@
+use log::{debug, warn};
+
+use crate::protocol::message::{Message, SafetyState};
+
+// percents, sticks within this are centred
+pub const NEUTRAL: f32 = 5.0;
+
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct DriveCommand {
+    pub left: f32,
+    pub right: f32,
+}
+
+impl DriveCommand {
+    pub const STOP: Self = Self {
+        left: 0.0,
+        right: 0.0,
+    };
+
+    pub fn is_neutral(&self) -> bool {
+        self.left.abs() <= NEUTRAL && self.right.abs() <= NEUTRAL
+    }
+}
+
+// Turns received messages into the drive command the motor loop should follow
+#[derive(Debug, Default, Clone, Copy)]
+pub struct Arbiter {
+    // what the sticks ask for, followed even while stopped so arming can check it
+    requested: DriveCommand,
+    emergency_stopped: bool,
+}
+
+impl Arbiter {
+    // emergency stopped until armed, as after a reset that kept the latch
+    pub fn latched() -> Self {
+        Self {
+            emergency_stopped: true,
+            ..Self::default()
+        }
+    }
+
+    pub fn handle(&mut self, message: &Message) -> DriveCommand {
+        match *message {
+            Message::LeftSpeed(speed) => self.requested.left = speed.clamp(-100.0, 100.0),
+            Message::RightSpeed(speed) => self.requested.right = speed.clamp(-100.0, 100.0),
+            // a car that is handed over stops
+            Message::Stop | Message::Release => self.requested = DriveCommand::STOP,
+            Message::EmergencyStop => {
+                warn!("emergency stop");
+                self.emergency_stopped = true;
+            }
+            // the car must not jump as soon as it is armed
+            Message::Arm if self.requested.is_neutral() => self.emergency_stopped = false,
+            Message::Arm => warn!("not armed, sticks aren't centred"),
+            Message::Battery(_)
+            | Message::Owner(_)
+            | Message::Safety(_)
+            | Message::Rssi(_)
+            | Message::Loss(_)
+            | Message::Crash(_)
+            | Message::Ping(_)
+            | Message::Pong(_)
+            | Message::Pair(_)
+            | Message::Hello(_)
+            | Message::ParamGet(_)
+            | Message::ParamSet(..)
+            | Message::ParamList
+            | Message::Param(_) => (),
+        }
+        debug!("command = {:?}", self.command());
+        self.command()
+    }
+
+    // no commands within the timeout
+    pub fn failsafe(&mut self) -> DriveCommand {
+        self.requested = DriveCommand::STOP;
+        self.command()
+    }
+
+    pub fn command(&self) -> DriveCommand {
+        if self.emergency_stopped {
+            DriveCommand::STOP
+        } else {
+            self.requested
+        }
+    }
+
+    pub fn safety(&self) -> SafetyState {
+        if self.emergency_stopped {
+            SafetyState::EmergencyStopped
+        } else {
+            SafetyState::Armed
+        }
+    }
+}
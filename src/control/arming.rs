@@ -0,0 +1,51 @@
+use embassy_time::{Duration, Instant};
+use log::info;
+
+use super::arbiter::DriveCommand;
+
+// Holds the car after boot or a failsafe until the sticks have been centred for a
+// while, so a remote started with a deflected stick doesn't launch it
+#[derive(Debug, Clone, Copy)]
+pub struct ArmingGate {
+    hold: Duration,
+    neutral_since: Option<Instant>,
+    armed: bool,
+}
+
+impl ArmingGate {
+    pub const fn new(hold: Duration) -> Self {
+        Self {
+            hold,
+            neutral_since: None,
+            armed: false,
+        }
+    }
+
+    // the command to follow, STOP while disarmed
+    pub fn update(&mut self, command: DriveCommand, now: Instant) -> DriveCommand {
+        if self.armed {
+            return command;
+        }
+        if !command.is_neutral() {
+            self.neutral_since = None;
+            return DriveCommand::STOP;
+        }
+
+        let since = *self.neutral_since.get_or_insert(now);
+        if now.saturating_duration_since(since) < self.hold {
+            return DriveCommand::STOP;
+        }
+        info!("armed");
+        self.armed = true;
+        command
+    }
+
+    pub fn disarm(&mut self) {
+        self.armed = false;
+        self.neutral_since = None;
+    }
+
+    pub fn is_armed(&self) -> bool {
+        self.armed
+    }
+}
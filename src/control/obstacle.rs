@@ -0,0 +1,70 @@
+use embassy_time::{Duration, Instant};
+
+// Scales down forward speed when an obstacle is close, reverse is never limited
+#[derive(Debug, Clone, Copy)]
+pub struct ObstacleLimiter {
+    // centimetres
+    stop_distance: f32,
+    slow_distance: f32,
+    hysteresis: f32,
+    // missed echoes for this long before nothing is taken to be in range
+    dropout: Duration,
+    last_echo: Option<Instant>,
+    scale: f32,
+}
+
+impl ObstacleLimiter {
+    pub const fn new(
+        stop_distance: f32,
+        slow_distance: f32,
+        hysteresis: f32,
+        dropout: Duration,
+    ) -> Self {
+        Self {
+            stop_distance,
+            slow_distance,
+            hysteresis,
+            dropout,
+            last_echo: None,
+            scale: 1.0,
+        }
+    }
+
+    // feeds a new measurement, None means nothing is in range
+    pub fn update(&mut self, distance: Option<f32>, now: Instant) {
+        let Some(distance) = distance else {
+            // a close or angled wall doesn't echo either, the last limit holds for a while
+            let expired = self
+                .last_echo
+                .is_none_or(|at| now.saturating_duration_since(at) >= self.dropout);
+            if expired {
+                self.last_echo = None;
+                self.scale = 1.0;
+            }
+            return;
+        };
+        self.last_echo = Some(now);
+
+        // once blocked, the obstacle has to move away past the hysteresis band
+        let release_distance = if self.is_blocked() {
+            self.stop_distance + self.hysteresis
+        } else {
+            self.stop_distance
+        };
+
+        self.scale = if distance <= release_distance {
+            0.0
+        } else {
+            ((distance - self.stop_distance) / (self.slow_distance - self.stop_distance))
+                .clamp(0.0, 1.0)
+        };
+    }
+
+    pub fn is_blocked(&self) -> bool {
+        self.scale == 0.0
+    }
+
+    pub fn limit(&self, speed: f32) -> f32 {
+        if speed > 0.0 { speed * self.scale } else { speed }
+    }
+}
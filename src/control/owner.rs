@@ -0,0 +1,52 @@
+use log::{info, warn};
+
+// Decides whose commands the car follows: only the bound remote, or while unbound
+// whoever talks first until it lets go
+#[derive(Debug, Default, Clone, Copy)]
+pub struct Ownership {
+    bound: Option<[u8; 6]>,
+    owner: Option<[u8; 6]>,
+    rejected: u32,
+}
+
+impl Ownership {
+    pub const fn new(bound: Option<[u8; 6]>) -> Self {
+        Self {
+            bound,
+            owner: bound,
+            rejected: 0,
+        }
+    }
+
+    pub fn accept(&mut self, src: &[u8; 6]) -> bool {
+        match self.owner {
+            Some(owner) if owner == *src => true,
+            Some(_) => {
+                self.rejected += 1;
+                warn!("{:02x?} rejected, {} so far", src, self.rejected);
+                false
+            }
+            None => {
+                info!("{:02x?} took control", src);
+                self.owner = Some(*src);
+                true
+            }
+        }
+    }
+
+    // lets the next sender take over, a bound remote keeps the car
+    pub fn release(&mut self) {
+        if self.bound.is_none() {
+            self.owner = None;
+        }
+    }
+
+    pub fn owner(&self) -> Option<[u8; 6]> {
+        self.owner
+    }
+
+    // commands dropped since boot
+    pub fn rejected(&self) -> u32 {
+        self.rejected
+    }
+}
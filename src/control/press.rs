@@ -0,0 +1,51 @@
+use embassy_time::{Duration, Instant};
+
+// shorter contacts are bounce
+const DEBOUNCE: Duration = Duration::from_millis(30);
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Press {
+    Short,
+    Long,
+}
+
+// Tells short presses from long ones on a sampled button. A short press is reported
+// on release, a long one as soon as it has been held long enough.
+#[derive(Debug, Clone, Copy)]
+pub struct PressDetector {
+    long: Duration,
+    pressed_at: Option<Instant>,
+    long_reported: bool,
+}
+
+impl PressDetector {
+    pub const fn new(long: Duration) -> Self {
+        Self {
+            long,
+            pressed_at: None,
+            long_reported: false,
+        }
+    }
+
+    pub fn update(&mut self, pressed: bool, now: Instant) -> Option<Press> {
+        match (pressed, self.pressed_at) {
+            (true, None) => {
+                self.pressed_at = Some(now);
+                self.long_reported = false;
+                None
+            }
+            (true, Some(at))
+                if !self.long_reported && now.saturating_duration_since(at) >= self.long =>
+            {
+                self.long_reported = true;
+                Some(Press::Long)
+            }
+            (false, Some(at)) => {
+                self.pressed_at = None;
+                let held = now.saturating_duration_since(at);
+                (!self.long_reported && held >= DEBOUNCE).then_some(Press::Short)
+            }
+            _ => None,
+        }
+    }
+}
@@ -0,0 +1,41 @@
+use embassy_time::{Duration, Instant};
+
+// Send-on-change with a keepalive, so airtime scales with how much the input moves
+// while the receiver's failsafe still sees regular traffic
+#[derive(Debug, Clone, Copy)]
+pub struct SendPolicy {
+    deadband: f32,
+    keepalive: Duration,
+    last: Option<(f32, Instant)>,
+}
+
+impl SendPolicy {
+    pub const fn new(deadband: f32, keepalive: Duration) -> Self {
+        Self {
+            deadband,
+            keepalive,
+            last: None,
+        }
+    }
+
+    pub fn should_send(&mut self, value: f32, now: Instant) -> bool {
+        let send = match self.last {
+            None => true,
+            Some((last_value, last_sent)) => {
+                (value - last_value).abs() > self.deadband
+                    // always resend the exact neutral position, so the car really stops
+                    || (value == 0.0 && last_value != 0.0)
+                    || now.saturating_duration_since(last_sent) >= self.keepalive
+            }
+        };
+        if send {
+            self.last = Some((value, now));
+        }
+        send
+    }
+
+    // the last value didn't get through, send the next one whatever it is
+    pub fn failed(&mut self) {
+        self.last = None;
+    }
+}
@@ -0,0 +1,59 @@
+use core::cell::Cell;
+
+use embassy_sync::blocking_mutex::{Mutex, raw::CriticalSectionRawMutex};
+use embassy_time::{Duration, Instant};
+
+// Checked in by a task each time round its loop, one that misses its deadline is
+// taken for hung
+pub struct Heartbeat {
+    name: &'static str,
+    deadline: Duration,
+    last: Mutex<CriticalSectionRawMutex, Cell<Option<Instant>>>,
+}
+
+impl Heartbeat {
+    pub const fn new(name: &'static str, deadline: Duration) -> Self {
+        Self {
+            name,
+            deadline,
+            last: Mutex::new(Cell::new(None)),
+        }
+    }
+
+    pub fn beat(&self) {
+        self.beat_at(Instant::now());
+    }
+
+    pub fn beat_at(&self, now: Instant) {
+        self.last.lock(|last| last.set(Some(now)));
+    }
+
+    pub fn name(&self) -> &'static str {
+        self.name
+    }
+
+    // a task that hasn't checked in yet is timed from `since`
+    fn is_late(&self, since: Instant, now: Instant) -> bool {
+        let last = self.last.lock(|last| last.get()).unwrap_or(since);
+        now.saturating_duration_since(last) > self.deadline
+    }
+}
+
+pub struct Supervisor<'a> {
+    tasks: &'a [&'a Heartbeat],
+    started: Instant,
+}
+
+impl<'a> Supervisor<'a> {
+    pub fn new(tasks: &'a [&'a Heartbeat], now: Instant) -> Self {
+        Self { tasks, started: now }
+    }
+
+    // the first task past its deadline
+    pub fn check(&self, now: Instant) -> Result<(), &'a Heartbeat> {
+        match self.tasks.iter().find(|task| task.is_late(self.started, now)) {
+            Some(task) => Err(task),
+            None => Ok(()),
+        }
+    }
+}
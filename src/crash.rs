@@ -0,0 +1,110 @@
+use core::{
+    fmt::{self, Write},
+    panic::PanicInfo,
+    sync::atomic::{AtomicBool, AtomicU32, Ordering},
+};
+
+use esp_hal::{
+    gpio::{AnyPin, Level, Output, OutputConfig, Pin},
+    ram,
+    system::software_reset,
+};
+use esp_println::println;
+use heapless::String;
+
+use crate::protocol::message::CRASH_LEN;
+
+// "CRSH", anything else in the record is power-up garbage
+const MAGIC: u32 = 0x4352_5348;
+
+// bit per GPIO that drives a motor
+static MOTOR_PINS: AtomicU32 = AtomicU32::new(0);
+static PANICKING: AtomicBool = AtomicBool::new(false);
+
+// survives the reset after a panic, not initialised at boot
+#[ram(rtc_fast, persistent)]
+static mut RECORD_MAGIC: u32 = 0;
+#[ram(rtc_fast, persistent)]
+static mut RECORD_LEN: u32 = 0;
+#[ram(rtc_fast, persistent)]
+static mut RECORD: [u8; CRASH_LEN] = [0; CRASH_LEN];
+
+// pulled low on a panic, before anything else can go wrong
+pub fn register_motor_pin(pin: &impl Pin) {
+    MOTOR_PINS.fetch_or(1 << pin.number(), Ordering::Relaxed);
+}
+
+// the crash before the last reset, if any; it's cleared so it's reported once
+pub fn take() -> Option<String<CRASH_LEN>> {
+    unsafe {
+        if (&raw const RECORD_MAGIC).read_volatile() != MAGIC {
+            return None;
+        }
+        (&raw mut RECORD_MAGIC).write_volatile(0);
+        let len = ((&raw const RECORD_LEN).read_volatile() as usize).min(CRASH_LEN);
+        let record = (&raw const RECORD).read_volatile();
+        core::str::from_utf8(&record[..len])
+            .ok()
+            .and_then(|record| String::try_from(record).ok())
+    }
+}
+
+// motors off, record, reboot
+pub fn on_panic(info: &PanicInfo) -> ! {
+    // a panic while handling one goes straight to the reset
+    if !PANICKING.swap(true, Ordering::Relaxed) {
+        stop_motors();
+        match info.location() {
+            Some(location) => record(format_args!(
+                "{}:{}: {}",
+                location.file(),
+                location.line(),
+                info.message()
+            )),
+            None => record(format_args!("{}", info.message())),
+        }
+        println!("{}", info);
+    }
+    software_reset()
+}
+
+// what `take` returns after the next reset
+pub fn record(what: fmt::Arguments) {
+    let mut recorder = Recorder { len: 0 };
+    let _ = recorder.write_fmt(what);
+    unsafe {
+        (&raw mut RECORD_LEN).write_volatile(recorder.len as u32);
+        (&raw mut RECORD_MAGIC).write_volatile(MAGIC);
+    }
+}
+
+// the registered motor pins, whatever the tasks driving them are doing
+pub fn stop_motors() {
+    let pins = MOTOR_PINS.load(Ordering::Relaxed);
+    for number in (0..32).filter(|number| pins & (1 << number) != 0) {
+        // taken away from the MCPWM whatever state its driver is in
+        let pin = unsafe { AnyPin::steal(number) };
+        core::mem::forget(Output::new(pin, Level::Low, OutputConfig::default()));
+    }
+}
+
+// writes into the record, cutting what doesn't fit
+struct Recorder {
+    len: usize,
+}
+
+impl Write for Recorder {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        for byte in s.bytes().take(CRASH_LEN - self.len) {
+            // the record is sent as a message value
+            let byte = match byte {
+                b';' => b',',
+                b' '..=b'~' => byte,
+                _ => b'?',
+            };
+            unsafe { (&raw mut RECORD).cast::<u8>().add(self.len).write_volatile(byte) };
+            self.len += 1;
+        }
+        Ok(())
+    }
+}
@@ -1 +1,3 @@
-pub mod motor;
\ No newline at end of file
+pub mod battery;
+pub mod motor;
+pub mod ultrasonic;
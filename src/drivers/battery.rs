@@ -0,0 +1,144 @@
+use log::{debug, warn};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Chemistry {
+    LiIon,
+    NiMh,
+    Alkaline,
+}
+
+// per cell, millivolts
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CellThresholds {
+    pub full: u16,
+    pub empty: u16,
+    pub low: u16,
+    pub cutoff: u16,
+}
+
+impl Chemistry {
+    pub const fn thresholds(self) -> CellThresholds {
+        match self {
+            Chemistry::LiIon => CellThresholds {
+                full: 4200,
+                empty: 3300,
+                low: 3500,
+                cutoff: 3300,
+            },
+            Chemistry::NiMh => CellThresholds {
+                full: 1400,
+                empty: 1000,
+                low: 1100,
+                cutoff: 1000,
+            },
+            Chemistry::Alkaline => CellThresholds {
+                full: 1600,
+                empty: 1000,
+                low: 1100,
+                cutoff: 900,
+            },
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BatteryConfig {
+    pub chemistry: Chemistry,
+    pub cells: u8,
+    // (R_top + R_bottom) / R_bottom of the voltage divider
+    pub divider_ratio: f32,
+    // exponential moving average weight of a new sample, 0..=1
+    pub filter: f32,
+    // per cell, millivolts
+    pub hysteresis: u16,
+    // motor power in percents while the battery is low
+    pub low_power_limit: f32,
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub enum BatteryState {
+    #[default]
+    Normal,
+    Low,
+    Cutoff,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct BatteryMonitor {
+    config: BatteryConfig,
+    voltage: Option<f32>,
+    state: BatteryState,
+}
+
+impl BatteryMonitor {
+    pub fn new(config: BatteryConfig) -> Self {
+        Self {
+            config,
+            voltage: None,
+            state: BatteryState::default(),
+        }
+    }
+
+    // takes the calibrated ADC pin voltage in millivolts
+    pub fn update(&mut self, pin_mv: u16) -> BatteryState {
+        let sample = pin_mv as f32 * self.config.divider_ratio;
+        let voltage = match self.voltage {
+            Some(voltage) => voltage + (sample - voltage) * self.config.filter,
+            None => sample,
+        };
+        self.voltage = Some(voltage);
+
+        let cells = self.config.cells as f32;
+        let thresholds = self.config.chemistry.thresholds();
+        let full = thresholds.full as f32 * cells;
+        let low = thresholds.low as f32 * cells;
+        let cutoff = thresholds.cutoff as f32 * cells;
+        let hysteresis = self.config.hysteresis as f32 * cells;
+
+        let state = match self.state {
+            BatteryState::Normal if voltage < cutoff => BatteryState::Cutoff,
+            BatteryState::Normal if voltage < low => BatteryState::Low,
+            BatteryState::Low if voltage < cutoff => BatteryState::Cutoff,
+            BatteryState::Low if voltage > low + hysteresis => BatteryState::Normal,
+            // a pack resting after the load recovers by more than any hysteresis,
+            // cut off stays until a reset or a charge
+            BatteryState::Cutoff if voltage > full - hysteresis => BatteryState::Normal,
+            state => state,
+        };
+
+        if state != self.state {
+            warn!("battery {:?} -> {:?} at {} mV", self.state, state, voltage as u32);
+            self.state = state;
+        }
+        debug!("battery = {} mV", voltage as u32);
+        state
+    }
+
+    pub fn state(&self) -> BatteryState {
+        self.state
+    }
+
+    pub fn voltage_mv(&self) -> u16 {
+        self.voltage.unwrap_or_default() as u16
+    }
+
+    pub fn state_of_charge(&self) -> u8 {
+        let Some(voltage) = self.voltage else {
+            return 0;
+        };
+        let cells = self.config.cells as f32;
+        let thresholds = self.config.chemistry.thresholds();
+        let full = thresholds.full as f32 * cells;
+        let empty = thresholds.empty as f32 * cells;
+        ((voltage - empty) * 100.0 / (full - empty)).clamp(0.0, 100.0) as u8
+    }
+
+    // maximal motor power in percents
+    pub fn power_limit(&self) -> f32 {
+        match self.state {
+            BatteryState::Normal => 100.0,
+            BatteryState::Low => self.config.low_power_limit,
+            BatteryState::Cutoff => 0.0,
+        }
+    }
+}
@@ -1,6 +1,8 @@
 use embedded_hal::pwm::SetDutyCycle;
 use log::debug;
 
+use crate::error::Error;
+
 #[derive(Debug, Default, Clone, Copy)]
 pub enum Direction {
     #[default]
@@ -44,14 +46,17 @@ where
         self.speed
     }
 
-    pub fn stop(&mut self) {
+    // both pins are tried even if the first one fails
+    pub fn stop(&mut self) -> Result<(), Error> {
         debug!("stop");
         self.speed = 0;
-        let _ = self.forward_pin.set_duty_cycle_fully_off();
-        let _ = self.backward_pin.set_duty_cycle_fully_off();
+        let forward = self.forward_pin.set_duty_cycle_fully_off();
+        let backward = self.backward_pin.set_duty_cycle_fully_off();
+        forward.map_err(|_| Error::Driver)?;
+        backward.map_err(|_| Error::Driver)
     }
 
-    pub fn run(&mut self, speed: i16) {
+    pub fn run(&mut self, speed: i16) -> Result<(), Error> {
         if speed < 0 {
             self.speed = -speed;
             self.set_dir(Direction::Backward);
@@ -64,7 +69,7 @@ where
             self.speed = 100;
         }
 
-        let (_, _) = match self.direction {
+        let (forward, backward) = match self.direction {
             Direction::Forward => (
                 self.forward_pin.set_duty_cycle_percent(self.speed as u8),
                 self.backward_pin.set_duty_cycle_percent(0),
@@ -74,5 +79,7 @@ where
                 self.backward_pin.set_duty_cycle_percent(self.speed as u8),
             ),
         };
+        forward.map_err(|_| Error::Driver)?;
+        backward.map_err(|_| Error::Driver)
     }
 }
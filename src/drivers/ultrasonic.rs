@@ -0,0 +1,49 @@
+use embassy_time::{Duration, Instant, Timer, with_timeout};
+use embedded_hal::digital::OutputPin;
+use embedded_hal_async::digital::Wait;
+use log::debug;
+
+// ~5 m round trip, the sensor gives up after ~38 ms anyway
+const ECHO_TIMEOUT: Duration = Duration::from_millis(30);
+const TRIGGER_PULSE: Duration = Duration::from_micros(10);
+
+// speed of sound: 58 us of echo per centimetre of distance
+const US_PER_CM: f32 = 58.0;
+
+// HC-SR04 style rangefinder
+#[derive(Debug)]
+pub struct Ultrasonic<T: OutputPin, E: Wait> {
+    trigger: T,
+    echo: E,
+}
+
+impl<T, E> Ultrasonic<T, E>
+where
+    T: OutputPin,
+    E: Wait,
+{
+    pub fn new(trigger: T, echo: E) -> Self {
+        Self { trigger, echo }
+    }
+
+    // distance in centimetres, None when nothing is in range
+    pub async fn measure(&mut self) -> Option<f32> {
+        let _ = self.trigger.set_high();
+        Timer::after(TRIGGER_PULSE).await;
+        let _ = self.trigger.set_low();
+
+        with_timeout(ECHO_TIMEOUT, self.echo.wait_for_high())
+            .await
+            .ok()?
+            .ok()?;
+        let start = Instant::now();
+        with_timeout(ECHO_TIMEOUT, self.echo.wait_for_low())
+            .await
+            .ok()?
+            .ok()?;
+
+        let distance = start.elapsed().as_micros() as f32 / US_PER_CM;
+        debug!("distance = {} cm", distance);
+        Some(distance)
+    }
+}
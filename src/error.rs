@@ -0,0 +1,75 @@
+use core::fmt;
+
+use crate::{protocol::parser::ParsingError, transport::TransportError};
+
+// What the run loops do about an error instead of panicking
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Recovery {
+    // transient, the same thing may work next time
+    Retry,
+    // the frame or value is lost, carry on with the next one
+    Drop,
+    // can't be trusted to drive any more, stop the motors
+    Failsafe,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Error {
+    // radio, BLE, web or UART link
+    Transport(TransportError),
+    // a frame that doesn't parse or verify
+    Protocol(ParsingError),
+    // flash couldn't be read or written
+    Config,
+    // a peripheral didn't take a setting
+    Driver,
+    // a message didn't fit its buffer
+    Format,
+    // the peer doesn't know the message, it isn't sent
+    Unsupported,
+}
+
+impl Error {
+    pub fn recovery(&self) -> Recovery {
+        match self {
+            Error::Transport(TransportError::Send | TransportError::Receive) => Recovery::Retry,
+            Error::Transport(TransportError::Peer | TransportError::FrameTooLong)
+            | Error::Protocol(_)
+            | Error::Config
+            | Error::Format
+            | Error::Unsupported => Recovery::Drop,
+            Error::Driver => Recovery::Failsafe,
+        }
+    }
+}
+
+impl From<TransportError> for Error {
+    fn from(err: TransportError) -> Self {
+        Error::Transport(err)
+    }
+}
+
+impl From<ParsingError> for Error {
+    fn from(err: ParsingError) -> Self {
+        Error::Protocol(err)
+    }
+}
+
+impl From<fmt::Error> for Error {
+    fn from(_: fmt::Error) -> Self {
+        Error::Format
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::Transport(err) => write!(f, "link: {:?}", err),
+            Error::Protocol(err) => write!(f, "protocol: {}", err),
+            Error::Config => write!(f, "config storage failed"),
+            Error::Driver => write!(f, "driver failed"),
+            Error::Format => write!(f, "message doesn't fit"),
+            Error::Unsupported => write!(f, "peer doesn't know the message"),
+        }
+    }
+}
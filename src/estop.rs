@@ -0,0 +1,18 @@
+use esp_hal::ram;
+
+// "ESTP", anything else is power-up garbage or a released latch
+const LATCHED: u32 = 0x4553_5450;
+
+// survives a reset, a panic or a watchdog, not a power cycle
+#[ram(rtc_fast, persistent)]
+static mut LATCH: u32 = 0;
+
+// an emergency stop that wasn't released by arming before the last reset
+pub fn is_latched() -> bool {
+    unsafe { (&raw const LATCH).read_volatile() == LATCHED }
+}
+
+pub fn set_latched(latched: bool) {
+    let value = if latched { LATCHED } else { 0 };
+    unsafe { (&raw mut LATCH).write_volatile(value) };
+}
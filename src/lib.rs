@@ -2,12 +2,23 @@
 
 pub mod protocol;
 pub mod drivers;
+pub mod control;
+pub mod board;
+pub mod transport;
+pub mod ble;
+pub mod config;
+pub mod params;
+pub mod pairing;
+pub mod crash;
+pub mod estop;
+pub mod error;
+#[cfg(feature = "web")]
+pub mod web;
 
 
 #[panic_handler]
 fn panic(info: &core::panic::PanicInfo) -> ! {
-    esp_println::dbg!("{?:}",info);
-    loop {}
+    crash::on_panic(info)
 }
 // from esp32 examples
 // When you are okay with using a nightly compiler it's better to use https://docs.rs/static_cell/2.1.0/static_cell/macro.make_static.html
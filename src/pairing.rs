@@ -0,0 +1,112 @@
+use embassy_time::{Duration, with_timeout};
+use hmac::{Hmac, Mac};
+use log::{info, warn};
+use sha2::Sha256;
+use x25519_dalek::{PublicKey, StaticSecret};
+
+use crate::{
+    config::Binding,
+    error::Error,
+    protocol::message::Message,
+    transport::{Inbox, PeerId, Transport, TransportError, send_message},
+};
+
+// ESP-NOW local master key
+pub type Lmk = [u8; 16];
+// signs the frames, see `Authenticator`
+pub type AuthKey = [u8; 32];
+
+// what a pairing agrees on, each key for one use only
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct LinkKeys {
+    pub lmk: Lmk,
+    pub auth: AuthKey,
+}
+
+// how often a remote repeats its request
+const RETRY: Duration = Duration::from_millis(200);
+
+// Both ends exchange X25519 public keys in the clear and compute the same keys from
+// their own secret and the other's public key, an eavesdropper has neither secret.
+// The keys aren't authenticated: whoever answers first while pairing is paired, so
+// pair out of reach of anyone else. None for a public key that would give keys
+// anyone can compute.
+pub fn derive_keys(
+    secret: &StaticSecret,
+    peer_key: &[u8; 32],
+    remote: &PeerId,
+    car: &PeerId,
+) -> Option<LinkKeys> {
+    let shared = secret.diffie_hellman(&PublicKey::from(*peer_key));
+    if !shared.was_contributory() {
+        return None;
+    }
+    let derive = |label: &[u8]| {
+        // HMAC takes keys of any length
+        let mut mac = Hmac::<Sha256>::new_from_slice(shared.as_bytes()).unwrap();
+        mac.update(label);
+        mac.update(remote);
+        mac.update(car);
+        mac.finalize().into_bytes()
+    };
+
+    let mut lmk = [0; 16];
+    lmk.copy_from_slice(&derive(b"robo_remote lmk")[..16]);
+    let mut auth = [0; 32];
+    auth.copy_from_slice(&derive(b"robo_remote auth"));
+    Some(LinkKeys { lmk, auth })
+}
+
+// Car side: answers the first pairing request heard with its own public key,
+// `secret` is fresh random bytes
+pub async fn pair_car<T: Transport>(
+    transport: &mut T,
+    secret: [u8; 32],
+) -> Result<Binding, Error> {
+    let secret = StaticSecret::from(secret);
+    let public = PublicKey::from(&secret).to_bytes();
+    loop {
+        let (remote, remote_key) = wait_for_pair(transport).await?;
+        let Some(keys) = derive_keys(&secret, &remote_key, &remote, &transport.local_id()) else {
+            warn!("weak pairing key from {:02x?}", remote);
+            continue;
+        };
+        send_message(transport, &remote, &Message::Pair(public)).await?;
+        info!("paired with {:02x?}", remote);
+        return Ok(Binding::new(remote, keys));
+    }
+}
+
+// Remote side: asks `to` until a car answers, broadcast finds whichever car is pairing
+pub async fn pair_remote<T: Transport>(
+    transport: &mut T,
+    to: &PeerId,
+    secret: [u8; 32],
+) -> Result<Binding, Error> {
+    let secret = StaticSecret::from(secret);
+    let public = PublicKey::from(&secret).to_bytes();
+    loop {
+        send_message(transport, to, &Message::Pair(public)).await?;
+        let Ok(answer) = with_timeout(RETRY, wait_for_pair(transport)).await else {
+            continue;
+        };
+        let (car, car_key) = answer?;
+        let Some(keys) = derive_keys(&secret, &car_key, &transport.local_id(), &car) else {
+            warn!("weak pairing key from {:02x?}", car);
+            continue;
+        };
+        info!("paired with {:02x?}", car);
+        return Ok(Binding::new(car, keys));
+    }
+}
+
+async fn wait_for_pair<T: Transport>(
+    transport: &mut T,
+) -> Result<(PeerId, [u8; 32]), TransportError> {
+    let mut inbox = Inbox::new();
+    loop {
+        if let (peer, Message::Pair(key)) = inbox.receive(transport).await? {
+            return Ok((peer, key));
+        }
+    }
+}
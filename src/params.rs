@@ -0,0 +1,199 @@
+use embedded_storage::{ReadStorage, Storage};
+use heapless::String;
+use log::warn;
+
+use crate::{
+    config::{CONFIG_OFFSET, checksum},
+    protocol::param::{ParamFlags, ParamInfo, ParamType, ParamValue},
+};
+
+// the flash sector after the config
+pub const PARAMS_OFFSET: u32 = CONFIG_OFFSET + 0x1000;
+
+pub const MAX_PARAMS: usize = 16;
+
+const MAGIC: [u8; 4] = *b"RRPM";
+const VERSION: u8 = 1;
+// id and value
+const ENTRY_LEN: usize = 1 + 4;
+// magic, version, entry count
+const HEADER_LEN: usize = 4 + 1 + 1;
+
+// an entry of the table, the value lives in `ParamTable`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ParamDef {
+    pub id: u8,
+    // without ',' or ';'
+    pub name: &'static str,
+    pub kind: ParamType,
+    pub min: f32,
+    pub max: f32,
+    pub default: ParamValue,
+    pub flags: ParamFlags,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ParamError {
+    Unknown,
+    ReadOnly,
+    WrongType,
+    OutOfRange,
+}
+
+#[derive(Clone)]
+pub struct ParamTable {
+    defs: &'static [ParamDef],
+    values: [ParamValue; MAX_PARAMS],
+}
+
+impl ParamTable {
+    pub const LEN: usize = HEADER_LEN + MAX_PARAMS * ENTRY_LEN + 1;
+
+    // every parameter at its default
+    pub const fn new(defs: &'static [ParamDef]) -> Self {
+        assert!(defs.len() <= MAX_PARAMS);
+        let mut values = [ParamValue::Bool(false); MAX_PARAMS];
+        let mut i = 0;
+        while i < defs.len() {
+            values[i] = defs[i].default;
+            i += 1;
+        }
+        Self { defs, values }
+    }
+
+    fn index(&self, id: u8) -> Option<usize> {
+        self.defs.iter().position(|def| def.id == id)
+    }
+
+    pub fn get(&self, id: u8) -> Option<ParamValue> {
+        self.index(id).map(|i| self.values[i])
+    }
+
+    // numeric value of a parameter the firmware defined itself
+    pub fn float(&self, id: u8) -> f32 {
+        self.get(id).map_or(0.0, |value| value.as_f32())
+    }
+
+    pub fn int(&self, id: u8) -> i32 {
+        self.float(id) as i32
+    }
+
+    pub fn info(&self, id: u8) -> Option<ParamInfo> {
+        let i = self.index(id)?;
+        let def = &self.defs[i];
+        Some(ParamInfo {
+            id: def.id,
+            name: String::try_from(def.name).ok()?,
+            kind: def.kind,
+            value: self.values[i],
+            min: def.min,
+            max: def.max,
+            default: def.default,
+            flags: def.flags,
+        })
+    }
+
+    pub fn ids(&self) -> impl Iterator<Item = u8> + '_ {
+        self.defs.iter().map(|def| def.id)
+    }
+
+    // whole numbers are taken for floats and the other way round, returns what was set
+    pub fn set(&mut self, id: u8, value: ParamValue) -> Result<ParamValue, ParamError> {
+        let i = self.index(id).ok_or(ParamError::Unknown)?;
+        let def = &self.defs[i];
+        if def.flags.contains(ParamFlags::READ_ONLY) {
+            return Err(ParamError::ReadOnly);
+        }
+        let value = match (def.kind, value) {
+            (ParamType::Bool, ParamValue::Bool(_)) => value,
+            (ParamType::Int, ParamValue::Int(_)) => value,
+            (ParamType::Int, ParamValue::Float(float)) if float.fract() == 0.0 => {
+                ParamValue::Int(float as i32)
+            }
+            (ParamType::Float, ParamValue::Float(_)) => value,
+            (ParamType::Float, ParamValue::Int(int)) => ParamValue::Float(int as f32),
+            _ => return Err(ParamError::WrongType),
+        };
+        if def.kind != ParamType::Bool && !(def.min..=def.max).contains(&value.as_f32()) {
+            return Err(ParamError::OutOfRange);
+        }
+        self.values[i] = value;
+        Ok(value)
+    }
+
+    pub fn persists(&self, id: u8) -> bool {
+        self.index(id)
+            .is_some_and(|i| self.defs[i].flags.contains(ParamFlags::PERSIST))
+    }
+
+    // header, the persisted entries, checksum
+    pub fn to_bytes(&self) -> [u8; Self::LEN] {
+        let mut bytes = [0; Self::LEN];
+        bytes[..4].copy_from_slice(&MAGIC);
+        bytes[4] = VERSION;
+        let mut count = 0;
+        for (def, value) in self.defs.iter().zip(self.values) {
+            if !def.flags.contains(ParamFlags::PERSIST) {
+                continue;
+            }
+            let at = HEADER_LEN + count * ENTRY_LEN;
+            let raw = match value {
+                ParamValue::Bool(value) => value as u32,
+                ParamValue::Int(value) => value as u32,
+                ParamValue::Float(value) => value.to_bits(),
+            };
+            bytes[at] = def.id;
+            bytes[at + 1..at + ENTRY_LEN].copy_from_slice(&raw.to_le_bytes());
+            count += 1;
+        }
+        bytes[5] = count as u8;
+        bytes[Self::LEN - 1] = checksum(&bytes[..Self::LEN - 1]);
+        bytes
+    }
+
+    // Keeps the defaults for erased flash, another layout or a torn write. Entries
+    // that aren't persisted or no longer fit the table are skipped.
+    pub fn apply_bytes(&mut self, bytes: &[u8; Self::LEN]) -> bool {
+        let count = bytes[5] as usize;
+        if bytes[..4] != MAGIC
+            || bytes[4] != VERSION
+            || count > MAX_PARAMS
+            || bytes[Self::LEN - 1] != checksum(&bytes[..Self::LEN - 1])
+        {
+            return false;
+        }
+
+        for n in 0..count {
+            let at = HEADER_LEN + n * ENTRY_LEN;
+            let id = bytes[at];
+            let mut raw = [0; 4];
+            raw.copy_from_slice(&bytes[at + 1..at + ENTRY_LEN]);
+            let raw = u32::from_le_bytes(raw);
+            let Some(i) = self.index(id).filter(|_| self.persists(id)) else {
+                continue;
+            };
+            let value = match self.defs[i].kind {
+                ParamType::Bool => ParamValue::Bool(raw != 0),
+                ParamType::Int => ParamValue::Int(raw as i32),
+                ParamType::Float => ParamValue::Float(f32::from_bits(raw)),
+            };
+            if let Err(err) = self.set(id, value) {
+                warn!("stored parameter {} dropped: {:?}", id, err);
+            }
+        }
+        true
+    }
+}
+
+pub fn load<S: ReadStorage>(storage: &mut S, table: &mut ParamTable) {
+    let mut bytes = [0; ParamTable::LEN];
+    if storage.read(PARAMS_OFFSET, &mut bytes).is_err() {
+        warn!("parameters can't be read");
+        return;
+    }
+    table.apply_bytes(&bytes);
+}
+
+pub fn save<S: Storage>(storage: &mut S, table: &ParamTable) -> Result<(), S::Error> {
+    storage.write(PARAMS_OFFSET, &table.to_bytes())
+}
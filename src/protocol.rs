@@ -1,3 +1,9 @@
+pub mod auth;
 pub mod comands;
+pub mod hello;
 pub mod message;
+pub mod param;
 pub mod parser;
+pub mod stream;
+pub mod tokenizer;
+pub mod validate;
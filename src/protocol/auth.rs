@@ -0,0 +1,151 @@
+use core::fmt;
+
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+
+use super::{
+    comands::{AUTH_PREFIX, EQ_VAL, SEPPARATOR},
+    parser::{ParsingError, Token},
+};
+
+pub const TAG_LEN: usize = 8;
+// "AUTH:" + 16 hex digits of sequence number + 16 of tag + ";"
+pub const TRAILER_LEN: usize = AUTH_PREFIX.len() + 1 + 16 + 2 * TAG_LEN + 1;
+
+// Signs outgoing frames and checks incoming ones with a key shared by both ends.
+// A frame is its messages followed by "AUTH:<seq><tag>;", the tag is a truncated
+// HMAC-SHA256 over the sequence number and everything before the trailer.
+pub struct Authenticator {
+    mac: Hmac<Sha256>,
+    next_seq: u64,
+    last_received: Option<u64>,
+    // upper half of the other end's sequence numbers, it grows with each of its boots
+    peer_epoch: Option<u32>,
+    lost: u32,
+}
+
+impl Authenticator {
+    // `first_seq` must grow across reboots, the other end rejects anything it has
+    // already seen a higher number than. `peer_epoch` is the other end's epoch as last
+    // stored, that one and older ones are refused so frames recorded before our reset
+    // can't be replayed after it.
+    pub fn new(key: &[u8], first_seq: u64, peer_epoch: Option<u32>) -> Self {
+        Self {
+            // HMAC takes keys of any length
+            mac: Hmac::new_from_slice(key).unwrap(),
+            next_seq: first_seq,
+            last_received: None,
+            peer_epoch,
+            lost: 0,
+        }
+    }
+
+    // trailer to append to `payload`
+    pub fn sign(&mut self, payload: &[u8]) -> Trailer {
+        let seq = self.next_seq;
+        self.next_seq += 1;
+
+        let mut tag = [0; TAG_LEN];
+        tag.copy_from_slice(&self.tag(seq, payload).finalize().into_bytes()[..TAG_LEN]);
+        Trailer { seq, tag }
+    }
+
+    // checks the trailer at the end of `frame` and returns what it covers
+    pub fn verify<'a>(&mut self, frame: &'a [u8]) -> Result<&'a [u8], ParsingError> {
+        let Some(payload_len) = frame.len().checked_sub(TRAILER_LEN) else {
+            return Err(ParsingError::NotAuthenticated);
+        };
+        let (payload, trailer) = frame.split_at(payload_len);
+
+        let (prefix, rest) = trailer.split_at(AUTH_PREFIX.len() + 1);
+        if &prefix[..AUTH_PREFIX.len()] != AUTH_PREFIX.as_bytes()
+            || prefix[AUTH_PREFIX.len()] != EQ_VAL as u8
+            || rest[rest.len() - 1] != SEPPARATOR as u8
+        {
+            return Err(ParsingError::NotAuthenticated);
+        }
+
+        let mut seq = [0; 8];
+        let mut tag = [0; TAG_LEN];
+        let digits = payload_len + prefix.len();
+        parse_hex(&rest[..16], &mut seq, digits)?;
+        parse_hex(&rest[16..16 + 2 * TAG_LEN], &mut tag, digits + 16)?;
+        let seq = u64::from_be_bytes(seq);
+
+        self.tag(seq, payload)
+            .verify_truncated_left(&tag)
+            .map_err(|_| ParsingError::BadTag)?;
+        let epoch = (seq >> 32) as u32;
+        let fresh = match self.last_received {
+            Some(last) => seq > last,
+            None => self.peer_epoch.is_none_or(|known| epoch > known),
+        };
+        if !fresh {
+            return Err(ParsingError::Replayed);
+        }
+        // the upper half changes when the other end reboots, that's no loss
+        if let Some(last) = self.last_received.filter(|last| last >> 32 == seq >> 32) {
+            self.lost = self.lost.saturating_add((seq - last - 1) as u32);
+        }
+        self.last_received = Some(seq);
+        self.peer_epoch = Some(epoch);
+        Ok(payload)
+    }
+
+    pub fn peer_epoch(&self) -> Option<u32> {
+        self.peer_epoch
+    }
+
+    // whether anything was accepted since we started
+    pub fn heard(&self) -> bool {
+        self.last_received.is_some()
+    }
+
+    // The other end refused our epoch, it restarted and remembers it. Our frames
+    // carry on in the next one.
+    pub fn next_epoch(&mut self) {
+        self.next_seq = ((self.next_seq >> 32) + 1) << 32;
+    }
+
+    // frames skipped in the sequence of verified ones
+    pub fn lost(&self) -> u32 {
+        self.lost
+    }
+
+    fn tag(&self, seq: u64, payload: &[u8]) -> Hmac<Sha256> {
+        let mut mac = self.mac.clone();
+        mac.update(&seq.to_le_bytes());
+        mac.update(payload);
+        mac
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Trailer {
+    pub seq: u64,
+    pub tag: [u8; TAG_LEN],
+}
+
+impl fmt::Display for Trailer {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{AUTH_PREFIX}{EQ_VAL}{:016x}", self.seq)?;
+        for byte in self.tag {
+            write!(f, "{byte:02x}")?;
+        }
+        write!(f, "{SEPPARATOR}")
+    }
+}
+
+// `offset` of the digits in the frame, for the error
+fn parse_hex(digits: &[u8], out: &mut [u8], offset: usize) -> Result<(), ParsingError> {
+    for (i, (byte, pair)) in out.iter_mut().zip(digits.chunks(2)).enumerate() {
+        *byte = core::str::from_utf8(pair)
+            .ok()
+            .and_then(|pair| u8::from_str_radix(pair, 16).ok())
+            .ok_or_else(|| ParsingError::ValueCanNotBeParsed {
+                offset: offset + 2 * i,
+                token: Token::new(pair),
+            })?;
+    }
+    Ok(())
+}
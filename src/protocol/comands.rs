@@ -2,7 +2,52 @@ pub const SEPPARATOR: char = ';';
 
 
 pub const STOP: &str = "STOP";
+pub const RELEASE: &str = "RELEASE";
+// latched until ARM
+pub const EMERGENCY_STOP: &str = "ESTOP";
+pub const ARM: &str = "ARM";
 pub const LEFT_SPEED_PREFIX: &str = "LSPEED";
 pub const RIGHT_SPEED_PREFIX: &str = "RSPEED";
 
+// telemetry
+pub const BATTERY_PREFIX: &str = "BATT";
+pub const OWNER_PREFIX: &str = "OWNER";
+pub const SAFETY_PREFIX: &str = "SAFETY";
+// values of SAFETY
+pub const ARMED: &str = "ARMED";
+pub const DISARMED: &str = "DISARMED";
+// link quality seen by the car
+pub const RSSI_PREFIX: &str = "RSSI";
+pub const LOSS_PREFIX: &str = "LOSS";
+// what made the car reboot
+pub const CRASH_PREFIX: &str = "CRASH";
+
+// round trip measurement, PONG echoes the value of PING
+pub const PING_PREFIX: &str = "PING";
+pub const PONG_PREFIX: &str = "PONG";
+
+// link setup
+pub const PAIR_PREFIX: &str = "PAIR";
+// protocol version and capabilities, see `hello`
+pub const HELLO_PREFIX: &str = "HELLO";
+// values of HELLO
+pub const CAR: &str = "CAR";
+pub const REMOTE: &str = "REMOTE";
+pub const BRIDGE: &str = "BRIDGE";
+
+// runtime parameters of the car, see `param`
+pub const PARAM_GET_PREFIX: &str = "PGET";
+pub const PARAM_SET_PREFIX: &str = "PSET";
+pub const PARAM_LIST: &str = "PLIST";
+// the car's answer to all three
+pub const PARAM_PREFIX: &str = "PARAM";
+// parameter types
+pub const BOOL: &str = "BOOL";
+pub const INT: &str = "INT";
+pub const FLOAT: &str = "FLOAT";
+// frame trailer, see `auth`
+pub const AUTH_PREFIX: &str = "AUTH";
+
 pub const EQ_VAL: char = ':';
+// between the fields of a value with several
+pub const FIELD_SEPPARATOR: char = ',';
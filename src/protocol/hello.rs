@@ -0,0 +1,137 @@
+use core::fmt;
+
+use heapless::String;
+
+use super::{
+    comands::{BRIDGE, CAR, FIELD_SEPPARATOR, REMOTE},
+    message::MessageKind,
+};
+
+// bumped whenever a keyword or value format in `comands` changes
+pub const PROTOCOL_VERSION: u8 = 2;
+// oldest protocol this firmware still talks to
+pub const MIN_PROTOCOL_VERSION: u8 = 1;
+pub const FIRMWARE_LEN: usize = 16;
+
+// what sent the hello
+#[derive(PartialEq, Debug, Clone, Copy)]
+pub enum Vehicle {
+    Car,
+    Remote,
+    Bridge,
+}
+
+impl Vehicle {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Vehicle::Car => CAR,
+            Vehicle::Remote => REMOTE,
+            Vehicle::Bridge => BRIDGE,
+        }
+    }
+
+    fn from_str(value: &str) -> Option<Self> {
+        match value {
+            CAR => Some(Vehicle::Car),
+            REMOTE => Some(Vehicle::Remote),
+            BRIDGE => Some(Vehicle::Bridge),
+            _ => None,
+        }
+    }
+}
+
+// set of message kinds, one bit each in `MessageKind` order
+#[derive(PartialEq, Debug, Clone, Copy)]
+pub struct Capabilities(u32);
+
+impl Capabilities {
+    pub const NONE: Self = Self(0);
+    // every message this firmware knows, up to the last kind
+    pub const ALL: Self = Self((1 << (MessageKind::Param as u32 + 1)) - 1);
+    // what driving safely needs, all a car from before the handshake is taken to know
+    pub const DRIVE: Self = Self::NONE
+        .with(MessageKind::LeftSpeed)
+        .with(MessageKind::RightSpeed)
+        .with(MessageKind::Stop)
+        .with(MessageKind::EmergencyStop)
+        .with(MessageKind::Arm)
+        .with(MessageKind::Release);
+
+    pub const fn with(self, kind: MessageKind) -> Self {
+        Self(self.0 | (1 << kind as u32))
+    }
+
+    pub fn supports(&self, kind: MessageKind) -> bool {
+        self.0 & (1 << kind as u32) != 0
+    }
+
+    pub fn contains(&self, other: Capabilities) -> bool {
+        self.0 & other.0 == other.0
+    }
+
+    // what both ends know
+    pub fn common(&self, other: Capabilities) -> Self {
+        Self(self.0 & other.0)
+    }
+}
+
+// how far a peer can be talked to
+#[derive(PartialEq, Debug, Clone, Copy)]
+pub enum Compatibility {
+    Full,
+    // only the messages both ends know
+    Degraded,
+    Incompatible,
+}
+
+#[derive(PartialEq, Debug, Clone)]
+pub struct Hello {
+    pub protocol: u8,
+    pub firmware: String<FIRMWARE_LEN>,
+    pub vehicle: Vehicle,
+    pub messages: Capabilities,
+}
+
+impl Hello {
+    // this firmware's
+    pub fn local(vehicle: Vehicle) -> Self {
+        Self {
+            protocol: PROTOCOL_VERSION,
+            firmware: String::try_from(env!("CARGO_PKG_VERSION")).unwrap_or_default(),
+            vehicle,
+            messages: Capabilities::ALL,
+        }
+    }
+
+    // `protocol,firmware,vehicle,messages` with the messages in hex
+    pub fn parse(value: &str) -> Option<Self> {
+        let mut fields = value.split(FIELD_SEPPARATOR);
+        let hello = Self {
+            protocol: fields.next()?.parse().ok()?,
+            firmware: String::try_from(fields.next()?).ok()?,
+            vehicle: Vehicle::from_str(fields.next()?)?,
+            messages: Capabilities(u32::from_str_radix(fields.next()?, 16).ok()?),
+        };
+        fields.next().is_none().then_some(hello)
+    }
+
+    // a peer that can't drive or is too old is refused, one on another
+    // protocol or with fewer messages is only sent what it knows
+    pub fn compatibility(&self) -> Compatibility {
+        if self.protocol < MIN_PROTOCOL_VERSION || !self.messages.contains(Capabilities::DRIVE) {
+            Compatibility::Incompatible
+        } else if self.protocol != PROTOCOL_VERSION || !self.messages.contains(Capabilities::ALL) {
+            Compatibility::Degraded
+        } else {
+            Compatibility::Full
+        }
+    }
+}
+
+impl fmt::Display for Hello {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let sep = FIELD_SEPPARATOR;
+        write!(f, "{}{sep}{}{sep}", self.protocol, self.firmware)?;
+        write!(f, "{}{sep}{:x}", self.vehicle.as_str(), self.messages.0)
+    }
+}
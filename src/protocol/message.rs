@@ -1,7 +1,174 @@
+use core::fmt;
+
+use heapless::String;
+
+use super::comands::{
+    ARM, ARMED, BATTERY_PREFIX, CRASH_PREFIX, DISARMED, EMERGENCY_STOP, EQ_VAL, FIELD_SEPPARATOR,
+    HELLO_PREFIX, LEFT_SPEED_PREFIX, LOSS_PREFIX, OWNER_PREFIX, PAIR_PREFIX, PARAM_GET_PREFIX,
+    PARAM_LIST, PARAM_PREFIX, PARAM_SET_PREFIX, PING_PREFIX, PONG_PREFIX, RELEASE,
+    RIGHT_SPEED_PREFIX, RSSI_PREFIX, SAFETY_PREFIX, SEPPARATOR, STOP,
+};
+use super::hello::Hello;
+use super::param::{ParamInfo, ParamValue};
+
+// longest crash record sent in telemetry
+pub const CRASH_LEN: usize = 96;
+
 #[derive(PartialEq,Debug,Default)]
 pub enum Message {
     LeftSpeed(f32),
     RightSpeed(f32),
     #[default]
     Stop,
+    // the controller hands the car over
+    Release,
+    // stops the car until it is armed again
+    EmergencyStop,
+    // only taken with the sticks centred
+    Arm,
+    // state of charge in percents
+    Battery(u8),
+    // address of the remote in control, if any
+    Owner(Option<[u8; 6]>),
+    Safety(SafetyState),
+    // dBm of the last frame from the controller
+    Rssi(i8),
+    // percents of frames lost since the last report
+    Loss(u8),
+    // panic location and message from before the last reboot, without ';'
+    Crash(String<CRASH_LEN>),
+    // answered with a Pong carrying the same value
+    Ping(u32),
+    Pong(u32),
+    // pairing request/answer with the sender's X25519 public key
+    Pair([u8; 32]),
+    // sent by the remote, answered by the car with its own
+    Hello(Hello),
+    // answered with a Param each
+    ParamGet(u8),
+    ParamSet(u8, ParamValue),
+    ParamList,
+    Param(ParamInfo),
+}
+
+// The variants without their values. The position is the bit in
+// `Capabilities`, so new ones only go at the end.
+#[derive(PartialEq, Debug, Clone, Copy)]
+pub enum MessageKind {
+    LeftSpeed,
+    RightSpeed,
+    Stop,
+    Release,
+    EmergencyStop,
+    Arm,
+    Battery,
+    Owner,
+    Safety,
+    Rssi,
+    Loss,
+    Crash,
+    Ping,
+    Pong,
+    Pair,
+    Hello,
+    ParamGet,
+    ParamSet,
+    ParamList,
+    Param,
+}
+
+impl Message {
+    pub fn kind(&self) -> MessageKind {
+        match self {
+            Message::LeftSpeed(_) => MessageKind::LeftSpeed,
+            Message::RightSpeed(_) => MessageKind::RightSpeed,
+            Message::Stop => MessageKind::Stop,
+            Message::Release => MessageKind::Release,
+            Message::EmergencyStop => MessageKind::EmergencyStop,
+            Message::Arm => MessageKind::Arm,
+            Message::Battery(_) => MessageKind::Battery,
+            Message::Owner(_) => MessageKind::Owner,
+            Message::Safety(_) => MessageKind::Safety,
+            Message::Rssi(_) => MessageKind::Rssi,
+            Message::Loss(_) => MessageKind::Loss,
+            Message::Crash(_) => MessageKind::Crash,
+            Message::Ping(_) => MessageKind::Ping,
+            Message::Pong(_) => MessageKind::Pong,
+            Message::Pair(_) => MessageKind::Pair,
+            Message::Hello(_) => MessageKind::Hello,
+            Message::ParamGet(_) => MessageKind::ParamGet,
+            Message::ParamSet(..) => MessageKind::ParamSet,
+            Message::ParamList => MessageKind::ParamList,
+            Message::Param(_) => MessageKind::Param,
+        }
+    }
+}
+
+// whether the car may move
+#[derive(PartialEq, Debug, Clone, Copy)]
+pub enum SafetyState {
+    Armed,
+    // waits for the sticks to be centred
+    Disarmed,
+    EmergencyStopped,
+}
+
+impl SafetyState {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            SafetyState::Armed => ARMED,
+            SafetyState::Disarmed => DISARMED,
+            SafetyState::EmergencyStopped => EMERGENCY_STOP,
+        }
+    }
+}
+
+// serializes the message into a frame the parser accepts
+impl fmt::Display for Message {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Message::LeftSpeed(speed) => {
+                write!(f, "{LEFT_SPEED_PREFIX}{EQ_VAL}{speed}{SEPPARATOR}")
+            }
+            Message::RightSpeed(speed) => {
+                write!(f, "{RIGHT_SPEED_PREFIX}{EQ_VAL}{speed}{SEPPARATOR}")
+            }
+            Message::Stop => write!(f, "{STOP}{EQ_VAL}{SEPPARATOR}"),
+            Message::Release => write!(f, "{RELEASE}{EQ_VAL}{SEPPARATOR}"),
+            Message::EmergencyStop => write!(f, "{EMERGENCY_STOP}{EQ_VAL}{SEPPARATOR}"),
+            Message::Arm => write!(f, "{ARM}{EQ_VAL}{SEPPARATOR}"),
+            Message::Battery(charge) => {
+                write!(f, "{BATTERY_PREFIX}{EQ_VAL}{charge}{SEPPARATOR}")
+            }
+            Message::Owner(owner) => {
+                write!(f, "{OWNER_PREFIX}{EQ_VAL}")?;
+                for byte in owner.iter().flatten() {
+                    write!(f, "{byte:02x}")?;
+                }
+                write!(f, "{SEPPARATOR}")
+            }
+            Message::Safety(state) => {
+                write!(f, "{SAFETY_PREFIX}{EQ_VAL}{}{SEPPARATOR}", state.as_str())
+            }
+            Message::Rssi(rssi) => write!(f, "{RSSI_PREFIX}{EQ_VAL}{rssi}{SEPPARATOR}"),
+            Message::Loss(loss) => write!(f, "{LOSS_PREFIX}{EQ_VAL}{loss}{SEPPARATOR}"),
+            Message::Crash(record) => write!(f, "{CRASH_PREFIX}{EQ_VAL}{record}{SEPPARATOR}"),
+            Message::Ping(value) => write!(f, "{PING_PREFIX}{EQ_VAL}{value}{SEPPARATOR}"),
+            Message::Pong(value) => write!(f, "{PONG_PREFIX}{EQ_VAL}{value}{SEPPARATOR}"),
+            Message::Pair(key) => {
+                write!(f, "{PAIR_PREFIX}{EQ_VAL}")?;
+                for byte in key {
+                    write!(f, "{byte:02x}")?;
+                }
+                write!(f, "{SEPPARATOR}")
+            }
+            Message::Hello(hello) => write!(f, "{HELLO_PREFIX}{EQ_VAL}{hello}{SEPPARATOR}"),
+            Message::ParamGet(id) => write!(f, "{PARAM_GET_PREFIX}{EQ_VAL}{id}{SEPPARATOR}"),
+            Message::ParamSet(id, value) => {
+                write!(f, "{PARAM_SET_PREFIX}{EQ_VAL}{id}{FIELD_SEPPARATOR}{value}{SEPPARATOR}")
+            }
+            Message::ParamList => write!(f, "{PARAM_LIST}{EQ_VAL}{SEPPARATOR}"),
+            Message::Param(info) => write!(f, "{PARAM_PREFIX}{EQ_VAL}{info}{SEPPARATOR}"),
+        }
+    }
 }
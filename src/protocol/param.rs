@@ -0,0 +1,156 @@
+use core::fmt;
+
+use heapless::String;
+
+use super::comands::{BOOL, FIELD_SEPPARATOR, FLOAT, INT};
+
+pub const NAME_LEN: usize = 16;
+
+#[derive(PartialEq, Debug, Clone, Copy)]
+pub enum ParamType {
+    Bool,
+    Int,
+    Float,
+}
+
+impl ParamType {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ParamType::Bool => BOOL,
+            ParamType::Int => INT,
+            ParamType::Float => FLOAT,
+        }
+    }
+
+    fn from_str(value: &str) -> Option<Self> {
+        match value {
+            BOOL => Some(ParamType::Bool),
+            INT => Some(ParamType::Int),
+            FLOAT => Some(ParamType::Float),
+            _ => None,
+        }
+    }
+}
+
+#[derive(PartialEq, Debug, Clone, Copy)]
+pub enum ParamValue {
+    Bool(bool),
+    Int(i32),
+    Float(f32),
+}
+
+impl ParamValue {
+    pub fn kind(&self) -> ParamType {
+        match self {
+            ParamValue::Bool(_) => ParamType::Bool,
+            ParamValue::Int(_) => ParamType::Int,
+            ParamValue::Float(_) => ParamType::Float,
+        }
+    }
+
+    pub fn as_f32(&self) -> f32 {
+        match *self {
+            ParamValue::Bool(value) => value as u8 as f32,
+            ParamValue::Int(value) => value as f32,
+            ParamValue::Float(value) => value,
+        }
+    }
+
+    // PSET doesn't say the type, the narrowest one that fits is taken
+    pub fn parse(value: &str) -> Option<Self> {
+        Self::parse_as(ParamType::Bool, value)
+            .or_else(|| Self::parse_as(ParamType::Int, value))
+            .or_else(|| Self::parse_as(ParamType::Float, value))
+    }
+
+    pub fn parse_as(kind: ParamType, value: &str) -> Option<Self> {
+        match kind {
+            ParamType::Bool => match value {
+                "true" => Some(ParamValue::Bool(true)),
+                "false" => Some(ParamValue::Bool(false)),
+                _ => None,
+            },
+            ParamType::Int => value.parse().ok().map(ParamValue::Int),
+            ParamType::Float => value.parse().ok().map(ParamValue::Float),
+        }
+    }
+}
+
+impl fmt::Display for ParamValue {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ParamValue::Bool(value) => write!(f, "{}", value),
+            ParamValue::Int(value) => write!(f, "{}", value),
+            ParamValue::Float(value) => write!(f, "{}", value),
+        }
+    }
+}
+
+#[derive(PartialEq, Debug, Clone, Copy)]
+pub struct ParamFlags(pub u8);
+
+impl ParamFlags {
+    pub const NONE: Self = Self(0);
+    // saved to flash when set
+    pub const PERSIST: Self = Self(1);
+    // only taken at boot
+    pub const REBOOT: Self = Self(1 << 1);
+    pub const READ_ONLY: Self = Self(1 << 2);
+
+    pub const fn with(self, other: ParamFlags) -> Self {
+        Self(self.0 | other.0)
+    }
+
+    pub fn contains(&self, other: ParamFlags) -> bool {
+        self.0 & other.0 == other.0
+    }
+}
+
+// a parameter as the car reports it in PARAM
+#[derive(PartialEq, Debug, Clone)]
+pub struct ParamInfo {
+    pub id: u8,
+    pub name: String<NAME_LEN>,
+    pub kind: ParamType,
+    pub value: ParamValue,
+    pub min: f32,
+    pub max: f32,
+    pub default: ParamValue,
+    pub flags: ParamFlags,
+}
+
+impl ParamInfo {
+    // `id,name,type,value,min,max,default,flags`
+    pub fn parse(value: &str) -> Option<Self> {
+        let mut fields = value.split(FIELD_SEPPARATOR);
+        let id = fields.next()?.parse().ok()?;
+        let name = String::try_from(fields.next()?).ok()?;
+        let kind = ParamType::from_str(fields.next()?)?;
+        let info = Self {
+            id,
+            name,
+            kind,
+            value: ParamValue::parse_as(kind, fields.next()?)?,
+            min: fields.next()?.parse().ok()?,
+            max: fields.next()?.parse().ok()?,
+            default: ParamValue::parse_as(kind, fields.next()?)?,
+            flags: ParamFlags(fields.next()?.parse().ok()?),
+        };
+        fields.next().is_none().then_some(info)
+    }
+}
+
+impl fmt::Display for ParamInfo {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let sep = FIELD_SEPPARATOR;
+        write!(f, "{}{sep}{}{sep}{}{sep}", self.id, self.name, self.kind.as_str())?;
+        write!(f, "{}{sep}{}{sep}{}{sep}", self.value, self.min, self.max)?;
+        write!(f, "{}{sep}{}", self.default, self.flags.0)
+    }
+}
+
+// `id,value` of PSET
+pub fn parse_set(value: &str) -> Option<(u8, ParamValue)> {
+    let (id, value) = value.split_once(FIELD_SEPPARATOR)?;
+    Some((id.parse().ok()?, ParamValue::parse(value)?))
+}
@@ -1,44 +1,156 @@
-use core::fmt;
+use core::{fmt, str};
+
+use heapless::String;
 
 use super::{
     comands::{
-        STOP, EQ_VAL, LEFT_SPEED_PREFIX, RIGHT_SPEED_PREFIX, SEPPARATOR,
+        ARM, ARMED, BATTERY_PREFIX, CRASH_PREFIX, DISARMED, EMERGENCY_STOP, HELLO_PREFIX, STOP,
+        LEFT_SPEED_PREFIX, LOSS_PREFIX, OWNER_PREFIX, PAIR_PREFIX, PARAM_GET_PREFIX, PARAM_LIST,
+        PARAM_PREFIX, PARAM_SET_PREFIX, PING_PREFIX, PONG_PREFIX, RELEASE, RIGHT_SPEED_PREFIX,
+        RSSI_PREFIX, SAFETY_PREFIX, SEPPARATOR,
     },
-    message::Message,
+    hello::Hello,
+    message::{Message, SafetyState},
+    param::{self, ParamInfo},
+    tokenizer::{RawMessage, Tokenizer},
+    validate,
 };
 
-#[derive(PartialEq)]
+// the offending bytes of a frame, cut to TOKEN_LEN, kept for error messages
+pub const TOKEN_LEN: usize = 16;
+
+#[derive(Clone, Copy, PartialEq)]
+pub struct Token {
+    bytes: [u8; TOKEN_LEN],
+    len: u8,
+    cut: bool,
+}
+
+impl Token {
+    pub fn new(bytes: &[u8]) -> Self {
+        let len = bytes.len().min(TOKEN_LEN);
+        let mut token = Self {
+            bytes: [0; TOKEN_LEN],
+            len: len as u8,
+            cut: bytes.len() > TOKEN_LEN,
+        };
+        token.bytes[..len].copy_from_slice(&bytes[..len]);
+        token
+    }
+
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.bytes[..self.len as usize]
+    }
+}
+
+// printable ASCII as it is, anything else escaped
+impl fmt::Display for Token {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for &byte in self.as_bytes() {
+            match byte {
+                b' '..=b'~' => write!(f, "{}", byte as char)?,
+                _ => write!(f, "\\x{byte:02x}")?,
+            }
+        }
+        if self.cut {
+            write!(f, "...")?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Debug for Token {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "\"{}\"", self)
+    }
+}
+
+// offsets are in bytes from the start of the frame
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub enum ParsingError {
-    NoSepparator,
-    ValueCanNotBeParsed,
-    NotAComand,
+    // `expected` is missing from `offset` on
+    NoSepparator { offset: usize, expected: char },
+    ValueCanNotBeParsed { offset: usize, token: Token },
+    NotAComand { offset: usize, token: Token },
+    // parses, but outside what `validate` allows
+    OutOfRange { offset: usize, token: Token },
+    // anything but printable ASCII and whitespace
+    NotAscii { offset: usize, byte: u8 },
+    // frame authentication, see `auth`
+    NotAuthenticated,
+    BadTag,
+    Replayed,
 }
 
 impl fmt::Display for ParsingError {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "Can't parse the message")
+        match self {
+            ParsingError::NoSepparator { offset, expected } => {
+                write!(f, "no '{}' from byte {} on", expected, offset)
+            }
+            ParsingError::ValueCanNotBeParsed { offset, token } => {
+                write!(f, "bad value \"{}\" at byte {}", token, offset)
+            }
+            ParsingError::NotAComand { offset, token } => {
+                write!(f, "unknown command \"{}\" at byte {}", token, offset)
+            }
+            ParsingError::OutOfRange { offset, token } => {
+                write!(f, "value \"{}\" at byte {} out of range", token, offset)
+            }
+            ParsingError::NotAscii { offset, byte } => {
+                write!(f, "unexpected byte 0x{:02x} at byte {}", byte, offset)
+            }
+            ParsingError::NotAuthenticated => write!(f, "no AUTH trailer"),
+            ParsingError::BadTag => write!(f, "AUTH tag doesn't match"),
+            ParsingError::Replayed => write!(f, "sequence number already seen"),
+        }
     }
 }
 
-pub fn parse(signal: &str) -> Result<Message, ParsingError> {
-    let Some(sep_idx) = get_sepparator_index(signal, SEPPARATOR) else {
-        return Err(ParsingError::NoSepparator);
-    };
-    let cut_part_1 = &signal[..sep_idx];
+impl core::error::Error for ParsingError {}
 
-    let Some(val_sep_idx) = get_sepparator_index(cut_part_1, EQ_VAL) else {
-        return Err(ParsingError::NoSepparator);
-    };
+// the first message of the frame
+pub fn parse(frame: &[u8]) -> Result<Message, ParsingError> {
+    match Tokenizer::new(frame).next() {
+        Some(raw) => parse_message(raw?),
+        None => Err(ParsingError::NoSepparator {
+            offset: frame.len(),
+            expected: SEPPARATOR,
+        }),
+    }
+}
 
-    let comand = &cut_part_1[..val_sep_idx];
-    let value = &cut_part_1[val_sep_idx + 1..];
+// every message of the frame, bad ones as errors
+pub fn parse_all(frame: &[u8]) -> impl Iterator<Item = Result<Message, ParsingError>> + '_ {
+    Tokenizer::new(frame).map(|raw| parse_message(raw?))
+}
 
-    match comand {
+pub fn parse_message(raw: RawMessage) -> Result<Message, ParsingError> {
+    let not_a_comand = || ParsingError::NotAComand {
+        offset: raw.key_offset,
+        token: Token::new(raw.key),
+    };
+    let bad_value = || ParsingError::ValueCanNotBeParsed {
+        offset: raw.value_offset,
+        token: Token::new(raw.value),
+    };
+    let comand = str::from_utf8(raw.key).map_err(|_| not_a_comand())?;
+    let value = str::from_utf8(raw.value).map_err(|_| bad_value())?;
+    // commands that carry nothing take nothing
+    let no_value = |message| {
+        if value.is_empty() {
+            Ok(message)
+        } else {
+            Err(bad_value())
+        }
+    };
+
+    let message = match comand {
         LEFT_SPEED_PREFIX => {
             if let Ok(speed) = value.parse::<f32>() {
                 Ok(Message::LeftSpeed(speed))
             } else {
-                Err(ParsingError::ValueCanNotBeParsed)
+                Err(bad_value())
             }
         }
 
@@ -46,21 +158,126 @@ pub fn parse(signal: &str) -> Result<Message, ParsingError> {
             if let Ok(speed) = value.parse::<f32>() {
                 Ok(Message::RightSpeed(speed))
             } else {
-                Err(ParsingError::ValueCanNotBeParsed)
+                Err(bad_value())
             }
         }
-        STOP => Ok(Message::Stop),
-        _ => Err(ParsingError::NotAComand),
+        STOP => no_value(Message::Stop),
+        RELEASE => no_value(Message::Release),
+        EMERGENCY_STOP => no_value(Message::EmergencyStop),
+        ARM => no_value(Message::Arm),
+        BATTERY_PREFIX => {
+            if let Ok(charge) = value.parse::<u8>() {
+                Ok(Message::Battery(charge))
+            } else {
+                Err(bad_value())
+            }
+        }
+        OWNER_PREFIX => {
+            if value.is_empty() {
+                Ok(Message::Owner(None))
+            } else if let Some(owner) = parse_bytes(value) {
+                Ok(Message::Owner(Some(owner)))
+            } else {
+                Err(bad_value())
+            }
+        }
+        SAFETY_PREFIX => match value {
+            ARMED => Ok(Message::Safety(SafetyState::Armed)),
+            DISARMED => Ok(Message::Safety(SafetyState::Disarmed)),
+            EMERGENCY_STOP => Ok(Message::Safety(SafetyState::EmergencyStopped)),
+            _ => Err(bad_value()),
+        },
+        RSSI_PREFIX => {
+            if let Ok(rssi) = value.parse::<i8>() {
+                Ok(Message::Rssi(rssi))
+            } else {
+                Err(bad_value())
+            }
+        }
+        LOSS_PREFIX => {
+            if let Ok(loss) = value.parse::<u8>() {
+                Ok(Message::Loss(loss))
+            } else {
+                Err(bad_value())
+            }
+        }
+        CRASH_PREFIX => {
+            if let Ok(record) = String::try_from(value) {
+                Ok(Message::Crash(record))
+            } else {
+                Err(bad_value())
+            }
+        }
+        PING_PREFIX => {
+            if let Ok(value) = value.parse::<u32>() {
+                Ok(Message::Ping(value))
+            } else {
+                Err(bad_value())
+            }
+        }
+        PONG_PREFIX => {
+            if let Ok(value) = value.parse::<u32>() {
+                Ok(Message::Pong(value))
+            } else {
+                Err(bad_value())
+            }
+        }
+        PAIR_PREFIX => {
+            if let Some(key) = parse_bytes(value) {
+                Ok(Message::Pair(key))
+            } else {
+                Err(bad_value())
+            }
+        }
+        HELLO_PREFIX => {
+            if let Some(hello) = Hello::parse(value) {
+                Ok(Message::Hello(hello))
+            } else {
+                Err(bad_value())
+            }
+        }
+        PARAM_GET_PREFIX => {
+            if let Ok(id) = value.parse::<u8>() {
+                Ok(Message::ParamGet(id))
+            } else {
+                Err(bad_value())
+            }
+        }
+        PARAM_SET_PREFIX => {
+            if let Some((id, value)) = param::parse_set(value) {
+                Ok(Message::ParamSet(id, value))
+            } else {
+                Err(bad_value())
+            }
+        }
+        PARAM_LIST => no_value(Message::ParamList),
+        PARAM_PREFIX => {
+            if let Some(info) = ParamInfo::parse(value) {
+                Ok(Message::Param(info))
+            } else {
+                Err(bad_value())
+            }
+        }
+        _ => Err(not_a_comand()),
+    }?;
+
+    if !validate::in_range(&message) {
+        return Err(ParsingError::OutOfRange {
+            offset: raw.value_offset,
+            token: Token::new(raw.value),
+        });
     }
+    Ok(message)
 }
 
-fn get_sepparator_index(string: &str, sepparator: char) -> Option<usize> {
-    let mut sep_index = None;
-    for (i, ch) in string.chars().enumerate() {
-        if ch == sepparator {
-            sep_index = Some(i);
-            break;
-        }
+// two hex digits a byte, no separators
+fn parse_bytes<const N: usize>(value: &str) -> Option<[u8; N]> {
+    if value.len() != 2 * N || !value.is_ascii() {
+        return None;
+    }
+    let mut bytes = [0; N];
+    for (i, byte) in bytes.iter_mut().enumerate() {
+        *byte = u8::from_str_radix(&value[2 * i..2 * i + 2], 16).ok()?;
     }
-    sep_index
+    Some(bytes)
 }
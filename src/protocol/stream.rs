@@ -0,0 +1,59 @@
+use heapless::Vec;
+
+use super::{
+    comands::SEPPARATOR,
+    message::Message,
+    parser::{ParsingError, parse},
+    tokenizer::is_space,
+};
+
+// Parses a byte stream that isn't cut into frames, a message may come in over
+// several reads. Bytes are kept until their ';' arrives, offsets in errors count
+// from the start of the message.
+#[derive(Default)]
+pub struct StreamParser<const N: usize> {
+    pending: Vec<u8, N>,
+    // dropping the rest of a message that didn't fit
+    skipping: bool,
+}
+
+impl<const N: usize> StreamParser<N> {
+    pub const fn new() -> Self {
+        Self {
+            pending: Vec::new(),
+            skipping: false,
+        }
+    }
+
+    // the message `byte` completes, if any
+    pub fn push(&mut self, byte: u8) -> Option<Result<Message, ParsingError>> {
+        let end = byte == SEPPARATOR as u8;
+        if self.skipping {
+            self.skipping = !end;
+            return None;
+        }
+        if self.pending.is_empty() && is_space(byte) {
+            return None;
+        }
+        if self.pending.push(byte).is_err() {
+            // no ';' within N bytes, resync on the next one
+            self.pending.clear();
+            self.skipping = !end;
+            return Some(Err(ParsingError::NoSepparator {
+                offset: 0,
+                expected: SEPPARATOR,
+            }));
+        }
+        if !end {
+            return None;
+        }
+        let res = parse(&self.pending);
+        self.pending.clear();
+        Some(res)
+    }
+
+    // bytes of the message still waiting for its ';'
+    pub fn pending(&self) -> usize {
+        self.pending.len()
+    }
+}
@@ -0,0 +1,88 @@
+use super::{
+    comands::{EQ_VAL, SEPPARATOR},
+    parser::ParsingError,
+};
+
+// a `KEY:VALUE;` message as it sits in the frame, trimmed
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RawMessage<'a> {
+    pub key: &'a [u8],
+    pub key_offset: usize,
+    pub value: &'a [u8],
+    pub value_offset: usize,
+}
+
+// Splits a frame into messages byte by byte. Spaces, tabs and line endings
+// around keys, values and messages are skipped; anything else outside
+// printable ASCII is an error. A bad message is reported and skipped, a
+// missing ';' ends the frame.
+pub struct Tokenizer<'a> {
+    frame: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Tokenizer<'a> {
+    pub fn new(frame: &'a [u8]) -> Self {
+        Self { frame, pos: 0 }
+    }
+
+    // carries on from `position()` of an earlier tokenizer over the same frame
+    pub fn resume(frame: &'a [u8], pos: usize) -> Self {
+        Self { frame, pos: pos.min(frame.len()) }
+    }
+
+    // offset of the first byte not consumed yet
+    pub fn position(&self) -> usize {
+        self.pos
+    }
+}
+
+pub fn is_space(byte: u8) -> bool {
+    matches!(byte, b' ' | b'\t' | b'\r' | b'\n')
+}
+
+fn is_text(byte: u8) -> bool {
+    matches!(byte, b' '..=b'~') || is_space(byte)
+}
+
+// `bytes` without surrounding whitespace and the offset of what's left
+fn trim(bytes: &[u8], offset: usize) -> (&[u8], usize) {
+    let start = bytes.iter().position(|&b| !is_space(b)).unwrap_or(bytes.len());
+    let end = bytes.iter().rposition(|&b| !is_space(b)).map_or(start, |i| i + 1);
+    (&bytes[start..end], offset + start)
+}
+
+impl<'a> Iterator for Tokenizer<'a> {
+    type Item = Result<RawMessage<'a>, ParsingError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let rest = &self.frame[self.pos..];
+        let start = self.pos + rest.iter().position(|&b| !is_space(b))?;
+        let rest = &self.frame[start..];
+
+        let Some(end) = rest.iter().position(|&b| b == SEPPARATOR as u8) else {
+            self.pos = self.frame.len();
+            if let Some(i) = rest.iter().position(|&b| !is_text(b)) {
+                return Some(Err(ParsingError::NotAscii { offset: start + i, byte: rest[i] }));
+            }
+            return Some(Err(ParsingError::NoSepparator { offset: start, expected: SEPPARATOR }));
+        };
+        self.pos = start + end + 1;
+        let message = &rest[..end];
+
+        if let Some(i) = message.iter().position(|&b| !is_text(b)) {
+            return Some(Err(ParsingError::NotAscii { offset: start + i, byte: message[i] }));
+        }
+        let Some(colon) = message.iter().position(|&b| b == EQ_VAL as u8) else {
+            return Some(Err(ParsingError::NoSepparator { offset: start, expected: EQ_VAL }));
+        };
+        let (key, key_offset) = trim(&message[..colon], start);
+        let (value, value_offset) = trim(&message[colon + 1..], start + colon + 1);
+        Some(Ok(RawMessage {
+            key,
+            key_offset,
+            value,
+            value_offset,
+        }))
+    }
+}
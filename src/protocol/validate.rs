@@ -0,0 +1,20 @@
+use core::ops::RangeInclusive;
+
+use super::{message::Message, param::ParamValue};
+
+// what a message may carry, anything else is `ParsingError::OutOfRange`
+pub const SPEED: RangeInclusive<f32> = -100.0..=100.0;
+pub const PERCENT: RangeInclusive<u8> = 0..=100;
+// dBm, the radio never reports a positive one
+pub const RSSI: RangeInclusive<i8> = i8::MIN..=0;
+
+// NaN and the infinities are in no range
+pub fn in_range(message: &Message) -> bool {
+    match message {
+        Message::LeftSpeed(speed) | Message::RightSpeed(speed) => SPEED.contains(speed),
+        Message::Battery(percent) | Message::Loss(percent) => PERCENT.contains(percent),
+        Message::Rssi(rssi) => RSSI.contains(rssi),
+        Message::ParamSet(_, ParamValue::Float(value)) => value.is_finite(),
+        _ => true,
+    }
+}
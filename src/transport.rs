@@ -0,0 +1,139 @@
+pub mod esp_now;
+pub mod latency;
+pub mod loopback;
+pub mod signed;
+pub mod uart;
+
+use core::fmt::Write;
+
+use heapless::{String, Vec};
+use log::warn;
+
+use crate::{
+    error::Error,
+    protocol::{message::Message, parser::parse_message, tokenizer::Tokenizer},
+};
+
+// ESP-NOW payload limit, the other links follow it
+pub const MAX_FRAME: usize = 250;
+
+// MAC address on the radio links, fixed per end on the wired ones
+pub type PeerId = [u8; 6];
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Frame {
+    pub peer: PeerId,
+    pub data: Vec<u8, MAX_FRAME>,
+    pub rssi: Option<i8>,
+}
+
+impl Frame {
+    pub fn new(peer: PeerId, data: &[u8]) -> Result<Self, TransportError> {
+        Ok(Self {
+            peer,
+            data: Vec::from_slice(data).map_err(|_| TransportError::FrameTooLong)?,
+            rssi: None,
+        })
+    }
+
+    pub fn from_message(peer: PeerId, message: &Message) -> Result<Self, Error> {
+        let mut data: String<MAX_FRAME> = String::new();
+        write!(&mut data, "{}", message)?;
+        Ok(Self::new(peer, data.as_bytes())?)
+    }
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct LinkMetrics {
+    pub sent: u32,
+    pub send_failed: u32,
+    pub received: u32,
+    // gaps in the sequence numbers, only known on signed links
+    pub lost: u32,
+    pub rssi: Option<i8>,
+}
+
+impl LinkMetrics {
+    pub const fn new() -> Self {
+        Self {
+            sent: 0,
+            send_failed: 0,
+            received: 0,
+            lost: 0,
+            rssi: None,
+        }
+    }
+
+    // percents of the frames expected since `earlier` that never arrived
+    pub fn loss_since(&self, earlier: &LinkMetrics) -> Option<u8> {
+        let received = self.received.wrapping_sub(earlier.received);
+        let lost = self.lost.wrapping_sub(earlier.lost);
+        let expected = received + lost;
+        (expected > 0).then(|| (lost as u64 * 100 / expected as u64) as u8)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TransportError {
+    Send,
+    Receive,
+    Peer,
+    FrameTooLong,
+}
+
+#[allow(async_fn_in_trait)]
+pub trait Transport {
+    async fn send(&mut self, peer: &PeerId, data: &[u8]) -> Result<(), TransportError>;
+
+    async fn receive(&mut self) -> Result<Frame, TransportError>;
+
+    // identity of this end of the link
+    fn local_id(&self) -> PeerId;
+
+    fn metrics(&self) -> LinkMetrics;
+}
+
+pub async fn send_message<T: Transport>(
+    transport: &mut T,
+    peer: &PeerId,
+    message: &Message,
+) -> Result<(), Error> {
+    let frame = Frame::from_message(*peer, message)?;
+    Ok(transport.send(peer, &frame.data).await?)
+}
+
+// A frame may carry several messages, the ones not handed out yet wait here.
+// Only `transport.receive()` is awaited, so a cancelled `receive` loses nothing.
+#[derive(Default)]
+pub struct Inbox {
+    frame: Option<Frame>,
+    pos: usize,
+}
+
+impl Inbox {
+    pub const fn new() -> Self {
+        Self { frame: None, pos: 0 }
+    }
+
+    // the next message that parses, frames are read as needed
+    pub async fn receive<T: Transport>(
+        &mut self,
+        transport: &mut T,
+    ) -> Result<(PeerId, Message), TransportError> {
+        loop {
+            if let Some(frame) = &self.frame {
+                let mut tokens = Tokenizer::resume(&frame.data, self.pos);
+                if let Some(raw) = tokens.next() {
+                    self.pos = tokens.position();
+                    match raw.and_then(parse_message) {
+                        Ok(message) => return Ok((frame.peer, message)),
+                        Err(err) => warn!("message from {:02x?} dropped: {}", frame.peer, err),
+                    }
+                    continue;
+                }
+            }
+            self.frame = Some(transport.receive().await?);
+            self.pos = 0;
+        }
+    }
+}
@@ -0,0 +1,111 @@
+use esp_wifi::esp_now::{BROADCAST_ADDRESS, EspNow, PeerInfo};
+use heapless::Vec;
+use log::{debug, warn};
+
+use super::{Frame, LinkMetrics, MAX_FRAME, PeerId, Transport, TransportError};
+use crate::config::{Binding, MAX_BINDINGS};
+
+pub struct EspNowTransport<'d> {
+    esp_now: EspNow<'d>,
+    address: PeerId,
+    metrics: LinkMetrics,
+    // the only peers listened to once paired
+    bound: Vec<PeerId, MAX_BINDINGS>,
+}
+
+impl<'d> EspNowTransport<'d> {
+    pub fn new(esp_now: EspNow<'d>, address: PeerId) -> Self {
+        Self {
+            esp_now,
+            address,
+            metrics: LinkMetrics::default(),
+            bound: Vec::new(),
+        }
+    }
+
+    // encrypts what we send to a paired peer with its key, from now on only unicasts
+    // from the paired peers are taken
+    pub fn bind(&mut self, binding: &Binding) -> Result<(), TransportError> {
+        let peer = PeerInfo {
+            peer_address: binding.peer,
+            lmk: Some(binding.lmk),
+            channel: None,
+            encrypt: true,
+        };
+        if self.esp_now.peer_exists(&binding.peer) {
+            self.esp_now.modify_peer(peer)
+        } else {
+            self.esp_now.add_peer(peer)
+        }
+        .map_err(|_| TransportError::Peer)?;
+
+        if !self.bound.contains(&binding.peer) {
+            self.bound.push(binding.peer).map_err(|_| TransportError::Peer)?;
+        }
+        Ok(())
+    }
+}
+
+impl Transport for EspNowTransport<'_> {
+    async fn send(&mut self, peer: &PeerId, data: &[u8]) -> Result<(), TransportError> {
+        if data.len() > MAX_FRAME {
+            return Err(TransportError::FrameTooLong);
+        }
+
+        // replies go to whoever talked to us
+        if !self.esp_now.peer_exists(peer) {
+            self.esp_now
+                .add_peer(PeerInfo {
+                    peer_address: *peer,
+                    lmk: None,
+                    channel: None,
+                    encrypt: false,
+                })
+                .map_err(|_| TransportError::Peer)?;
+        }
+
+        match self.esp_now.send_async(peer, data).await {
+            Ok(()) => {
+                self.metrics.sent += 1;
+                Ok(())
+            }
+            Err(_) => {
+                self.metrics.send_failed += 1;
+                Err(TransportError::Send)
+            }
+        }
+    }
+
+    async fn receive(&mut self) -> Result<Frame, TransportError> {
+        loop {
+            let rec = self.esp_now.receive_async().await;
+            if rec.info.dst_address != self.address && rec.info.dst_address != BROADCAST_ADDRESS {
+                debug!("frame for {:02x?} dropped", rec.info.dst_address);
+                continue;
+            }
+            // ESP-NOW doesn't tell whether a frame was encrypted, this only goes by
+            // the addresses; the signature checked above us is what proves the sender
+            if !self.bound.is_empty()
+                && (!self.bound.contains(&rec.info.src_address)
+                    || rec.info.dst_address == BROADCAST_ADDRESS)
+            {
+                warn!("frame from unpaired {:02x?} or broadcast dropped", rec.info.src_address);
+                continue;
+            }
+
+            let mut frame = Frame::new(rec.info.src_address, rec.data())?;
+            frame.rssi = Some(rec.info.rx_control.rssi as i8);
+            self.metrics.received += 1;
+            self.metrics.rssi = frame.rssi;
+            return Ok(frame);
+        }
+    }
+
+    fn local_id(&self) -> PeerId {
+        self.address
+    }
+
+    fn metrics(&self) -> LinkMetrics {
+        self.metrics
+    }
+}
@@ -0,0 +1,59 @@
+use embassy_time::Duration;
+
+// Round trips of Ping/Pong, summarised per measurement window
+#[derive(Debug, Default, Clone, Copy)]
+pub struct LatencyStats {
+    count: u32,
+    min: Duration,
+    max: Duration,
+    total: Duration,
+    // sum of the differences between consecutive round trips
+    variation: Duration,
+    last: Option<Duration>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct LatencySummary {
+    pub samples: u32,
+    pub min: Duration,
+    pub avg: Duration,
+    pub max: Duration,
+    // mean difference between consecutive round trips
+    pub jitter: Duration,
+}
+
+impl LatencyStats {
+    pub fn record(&mut self, rtt: Duration) {
+        if self.count == 0 {
+            self.min = rtt;
+            self.max = rtt;
+        } else {
+            self.min = self.min.min(rtt);
+            self.max = self.max.max(rtt);
+        }
+        if let Some(last) = self.last {
+            self.variation += if rtt > last { rtt - last } else { last - rtt };
+        }
+        self.count += 1;
+        self.total += rtt;
+        self.last = Some(rtt);
+    }
+
+    pub fn summary(&self) -> Option<LatencySummary> {
+        (self.count > 0).then(|| LatencySummary {
+            samples: self.count,
+            min: self.min,
+            avg: self.total / self.count,
+            max: self.max,
+            jitter: if self.count > 1 {
+                self.variation / (self.count - 1)
+            } else {
+                Duration::from_ticks(0)
+            },
+        })
+    }
+
+    pub fn reset(&mut self) {
+        *self = Self::default();
+    }
+}
@@ -0,0 +1,135 @@
+use core::fmt::Write as _;
+
+use heapless::{String, Vec};
+use log::warn;
+
+use super::{Frame, LinkMetrics, MAX_FRAME, PeerId, Transport, TransportError};
+use crate::{
+    config::MAX_BINDINGS,
+    protocol::{
+        auth::{Authenticator, TRAILER_LEN},
+        parser::ParsingError,
+    },
+};
+
+// Signs everything sent and drops received frames that fail verification, each peer
+// with its own key. Without any peers frames pass through untouched.
+//
+// A restarted peer refuses our epoch until we move to a new one, it tells us so with
+// an empty signed frame.
+pub struct SignedTransport<T: Transport> {
+    inner: T,
+    peers: Vec<(PeerId, Authenticator), MAX_BINDINGS>,
+    // told about each new epoch of a peer, to store it
+    epoch_hook: Option<fn(PeerId, u32)>,
+}
+
+impl<T: Transport> SignedTransport<T> {
+    pub fn new(inner: T) -> Self {
+        Self {
+            inner,
+            peers: Vec::new(),
+            epoch_hook: None,
+        }
+    }
+
+    pub fn add_peer(&mut self, peer: PeerId, auth: Authenticator) -> Result<(), TransportError> {
+        self.peers.retain(|(known, _)| *known != peer);
+        self.peers
+            .push((peer, auth))
+            .map_err(|_| TransportError::Peer)
+    }
+
+    pub fn on_new_epoch(&mut self, hook: fn(PeerId, u32)) {
+        self.epoch_hook = Some(hook);
+    }
+
+    fn authenticator(&mut self, peer: &PeerId) -> Option<&mut Authenticator> {
+        self.peers
+            .iter_mut()
+            .find_map(|(known, auth)| (known == peer).then_some(auth))
+    }
+
+    // `data` followed by its trailer
+    fn sign(&mut self, peer: &PeerId, data: &[u8]) -> Result<Vec<u8, MAX_FRAME>, TransportError> {
+        let Some(auth) = self.authenticator(peer) else {
+            return Err(TransportError::Peer);
+        };
+        if data.len() + TRAILER_LEN > MAX_FRAME {
+            return Err(TransportError::FrameTooLong);
+        }
+
+        let mut trailer: String<TRAILER_LEN> = String::new();
+        let mut frame: Vec<u8, MAX_FRAME> = Vec::new();
+        write!(&mut trailer, "{}", auth.sign(data)).map_err(|_| TransportError::FrameTooLong)?;
+        frame
+            .extend_from_slice(data)
+            .and_then(|()| frame.extend_from_slice(trailer.as_bytes()))
+            .map_err(|_| TransportError::FrameTooLong)?;
+        Ok(frame)
+    }
+}
+
+impl<T: Transport> Transport for SignedTransport<T> {
+    async fn send(&mut self, peer: &PeerId, data: &[u8]) -> Result<(), TransportError> {
+        if self.peers.is_empty() {
+            return self.inner.send(peer, data).await;
+        }
+        let frame = self.sign(peer, data)?;
+        self.inner.send(peer, &frame).await
+    }
+
+    async fn receive(&mut self) -> Result<Frame, TransportError> {
+        loop {
+            let mut frame = self.inner.receive().await?;
+            if self.peers.is_empty() {
+                return Ok(frame);
+            }
+            let hook = self.epoch_hook;
+            let Some(auth) = self.authenticator(&frame.peer) else {
+                warn!("frame from unknown {:02x?} dropped", frame.peer);
+                continue;
+            };
+            let known_epoch = auth.peer_epoch();
+            match auth.verify(&frame.data) {
+                Ok(payload) => {
+                    let len = payload.len();
+                    let epoch = auth.peer_epoch().filter(|epoch| Some(*epoch) != known_epoch);
+                    if let (Some(hook), Some(epoch)) = (hook, epoch) {
+                        hook(frame.peer, epoch);
+                    }
+                    if len == 0 {
+                        auth.next_epoch();
+                        continue;
+                    }
+                    frame.data.truncate(len);
+                    return Ok(frame);
+                }
+                // we restarted and it doesn't know yet, or someone replays it
+                Err(ParsingError::Replayed) if !auth.heard() => {
+                    let sent = match self.sign(&frame.peer, &[]) {
+                        Ok(nudge) => self.inner.send(&frame.peer, &nudge).await,
+                        Err(err) => Err(err),
+                    };
+                    if let Err(err) = sent {
+                        warn!("epoch nudge to {:02x?} failed: {:?}", frame.peer, err);
+                    }
+                }
+                Err(err) => warn!("frame from {:02x?} dropped: {}", frame.peer, err),
+            }
+        }
+    }
+
+    fn local_id(&self) -> PeerId {
+        self.inner.local_id()
+    }
+
+    fn metrics(&self) -> LinkMetrics {
+        let mut metrics = self.inner.metrics();
+        metrics.lost = self
+            .peers
+            .iter()
+            .fold(0, |lost, (_, auth)| lost.saturating_add(auth.lost()));
+        metrics
+    }
+}
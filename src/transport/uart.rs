@@ -0,0 +1,108 @@
+use embedded_io_async::{Read, Write};
+use heapless::Vec;
+
+use super::{Frame, LinkMetrics, MAX_FRAME, PeerId, Transport, TransportError};
+
+pub const FRAME_END: u8 = b'\n';
+
+// Point to point link, frames are separated by a line feed
+pub struct UartTransport<T: Read + Write> {
+    uart: T,
+    local: PeerId,
+    remote: PeerId,
+    pending: Vec<u8, MAX_FRAME>,
+    metrics: LinkMetrics,
+}
+
+impl<T> UartTransport<T>
+where
+    T: Read + Write,
+{
+    pub fn new(uart: T, local: PeerId, remote: PeerId) -> Self {
+        Self {
+            uart,
+            local,
+            remote,
+            pending: Vec::new(),
+            metrics: LinkMetrics::default(),
+        }
+    }
+
+    fn take_frame(&mut self) -> Option<Frame> {
+        let end = self.pending.iter().position(|&byte| byte == FRAME_END)?;
+        let frame = Frame::new(self.remote, &self.pending[..end]).ok()?;
+        let rest = self.pending.len() - end - 1;
+        self.pending.copy_within(end + 1.., 0);
+        self.pending.truncate(rest);
+        Some(frame)
+    }
+
+    // bytes as they come, for peers that stream messages without FRAME_END
+    pub async fn read(&mut self, buf: &mut [u8]) -> Result<usize, TransportError> {
+        if self.pending.is_empty() {
+            return self.uart.read(buf).await.map_err(|_| TransportError::Receive);
+        }
+        let len = self.pending.len().min(buf.len());
+        buf[..len].copy_from_slice(&self.pending[..len]);
+        let rest = self.pending.len() - len;
+        self.pending.copy_within(len.., 0);
+        self.pending.truncate(rest);
+        Ok(len)
+    }
+}
+
+impl<T> Transport for UartTransport<T>
+where
+    T: Read + Write,
+{
+    async fn send(&mut self, _peer: &PeerId, data: &[u8]) -> Result<(), TransportError> {
+        if data.len() > MAX_FRAME {
+            return Err(TransportError::FrameTooLong);
+        }
+
+        let data = data.strip_suffix(&[FRAME_END]).unwrap_or(data);
+        let res = match self.uart.write_all(data).await {
+            Ok(()) => self.uart.write_all(&[FRAME_END]).await,
+            Err(err) => Err(err),
+        };
+        match res {
+            Ok(()) => {
+                self.metrics.sent += 1;
+                Ok(())
+            }
+            Err(_) => {
+                self.metrics.send_failed += 1;
+                Err(TransportError::Send)
+            }
+        }
+    }
+
+    async fn receive(&mut self) -> Result<Frame, TransportError> {
+        let mut buf = [0u8; 32];
+        loop {
+            if let Some(frame) = self.take_frame() {
+                self.metrics.received += 1;
+                return Ok(frame);
+            }
+
+            let len = self
+                .uart
+                .read(&mut buf)
+                .await
+                .map_err(|_| TransportError::Receive)?;
+            if self.pending.extend_from_slice(&buf[..len]).is_err() {
+                // no line feed within a whole frame, resync on the next one
+                self.pending.clear();
+                return Err(TransportError::FrameTooLong);
+            }
+        }
+    }
+
+    fn local_id(&self) -> PeerId {
+        self.local
+    }
+
+    fn metrics(&self) -> LinkMetrics {
+        self.metrics
+    }
+}
@@ -0,0 +1,260 @@
+pub mod http;
+pub mod websocket;
+
+use core::{
+    fmt::{self, Write as _},
+    net::{Ipv4Addr, SocketAddr, SocketAddrV4},
+};
+
+use edge_dhcp::{
+    io::{self, DEFAULT_SERVER_PORT},
+    server::{Server, ServerOptions},
+};
+use edge_nal::UdpBind;
+use edge_nal_embassy::{Udp, UdpBuffers};
+use embassy_futures::select::{Either, select};
+use embassy_net::{Stack, tcp::TcpSocket};
+use embassy_time::{Duration, Timer};
+use embedded_io_async::Write;
+use esp_wifi::wifi::{
+    AccessPointConfiguration, AuthMethod, Configuration, WifiController, WifiEvent, WifiState,
+};
+use heapless::String;
+use log::{info, warn};
+
+use self::{
+    http::{HttpError, parse_request},
+    websocket::{Opcode, accept_key, decode, encode},
+};
+use crate::transport::{PeerId, Transport};
+
+// what the car side of the link sees as the sender of browser commands
+pub const WEB_PEER: PeerId = [0x3e, 0xb0, 0x00, 0x00, 0x00, 0x01];
+
+pub const GATEWAY: Ipv4Addr = Ipv4Addr::new(192, 168, 4, 1);
+const HTTP_PORT: u16 = 80;
+
+const INDEX: &str = include_str!("web/index.html");
+
+const BUFFER_LEN: usize = 1024;
+// the page sends every 100 ms, a silent socket is a dead one
+const SOCKET_TIMEOUT: Duration = Duration::from_secs(5);
+
+// WPA2 won't take a shorter passphrase
+pub const MIN_PASSWORD_LEN: usize = 8;
+
+// Keeps the soft-AP up, restarting it when it stops. Whoever joins can drive the car,
+// so it's never open.
+pub async fn run_access_point(
+    mut controller: WifiController<'static>,
+    ssid: &str,
+    password: &str,
+    channel: u8,
+) -> ! {
+    loop {
+        if matches!(esp_wifi::wifi::wifi_state(), WifiState::ApStarted) {
+            controller.wait_for_event(WifiEvent::ApStop).await;
+            Timer::after(Duration::from_secs(1)).await;
+        }
+        if !matches!(controller.is_started(), Ok(true)) {
+            let config = Configuration::AccessPoint(AccessPointConfiguration {
+                ssid: ssid.try_into().unwrap(),
+                password: password.try_into().unwrap(),
+                auth_method: AuthMethod::WPA2Personal,
+                channel,
+                ..Default::default()
+            });
+            controller.set_configuration(&config).unwrap();
+            info!("starting access point {}", ssid);
+            if let Err(err) = controller.start_async().await {
+                warn!("access point: {:?}", err);
+            }
+        }
+    }
+}
+
+// Hands out addresses to the browsers' machines
+pub async fn run_dhcp(stack: Stack<'static>) -> ! {
+    let mut buf = [0u8; 1500];
+    let mut gateways = [Ipv4Addr::UNSPECIFIED];
+    let buffers = UdpBuffers::<2, 1024, 1024, 4>::new();
+    let udp = Udp::new(stack, &buffers);
+    let mut socket = udp
+        .bind(SocketAddr::V4(SocketAddrV4::new(
+            Ipv4Addr::UNSPECIFIED,
+            DEFAULT_SERVER_PORT,
+        )))
+        .await
+        .unwrap();
+
+    loop {
+        if let Err(err) = io::server::run(
+            &mut Server::<_, 8>::new_with_et(GATEWAY),
+            &ServerOptions::new(GATEWAY, Some(&mut gateways)),
+            &mut socket,
+            &mut buf,
+        )
+        .await
+        {
+            warn!("dhcp: {:?}", err);
+        }
+        Timer::after(Duration::from_millis(500)).await;
+    }
+}
+
+// Serves the control page and bridges its WebSocket to `link`, one client at a time
+pub async fn run_server<T: Transport>(stack: Stack<'static>, mut link: T) -> ! {
+    let mut rx_buffer = [0u8; BUFFER_LEN];
+    let mut tx_buffer = [0u8; BUFFER_LEN];
+    loop {
+        let mut socket = TcpSocket::new(stack, &mut rx_buffer, &mut tx_buffer);
+        socket.set_timeout(Some(SOCKET_TIMEOUT));
+        if let Err(err) = socket.accept(HTTP_PORT).await {
+            warn!("http accept: {:?}", err);
+            continue;
+        }
+        if let Err(err) = handle(&mut socket, &mut link).await {
+            warn!("http: {}", err);
+        }
+        socket.close();
+        let _ = socket.flush().await;
+        socket.abort();
+    }
+}
+
+enum ServerError {
+    Socket(embassy_net::tcp::Error),
+    Http(HttpError),
+    WebSocket(websocket::WebSocketError),
+}
+
+impl fmt::Display for ServerError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ServerError::Socket(err) => write!(f, "socket {:?}", err),
+            ServerError::Http(err) => write!(f, "request {:?}", err),
+            ServerError::WebSocket(err) => write!(f, "websocket {:?}", err),
+        }
+    }
+}
+
+impl From<embassy_net::tcp::Error> for ServerError {
+    fn from(err: embassy_net::tcp::Error) -> Self {
+        ServerError::Socket(err)
+    }
+}
+
+impl From<HttpError> for ServerError {
+    fn from(err: HttpError) -> Self {
+        ServerError::Http(err)
+    }
+}
+
+impl From<websocket::WebSocketError> for ServerError {
+    fn from(err: websocket::WebSocketError) -> Self {
+        ServerError::WebSocket(err)
+    }
+}
+
+async fn handle<T: Transport>(socket: &mut TcpSocket<'_>, link: &mut T) -> Result<(), ServerError> {
+    let mut buf = [0u8; BUFFER_LEN];
+    let mut len = 0;
+    let request = loop {
+        if len == buf.len() {
+            return Err(HttpError::TooLong.into());
+        }
+        let read = socket.read(&mut buf[len..]).await?;
+        if read == 0 {
+            return Ok(());
+        }
+        len += read;
+        if let Some(request) = parse_request(&buf[..len])? {
+            break request;
+        }
+    };
+    info!("http {} {}", request.method, request.path);
+
+    match (request.method, request.path, request.websocket_key) {
+        ("GET", "/ws", Some(key)) => {
+            let accept = accept_key(key.as_bytes());
+            let mut response: String<160> = String::new();
+            write!(
+                &mut response,
+                "HTTP/1.1 101 Switching Protocols\r\nUpgrade: websocket\r\nConnection: Upgrade\r\nSec-WebSocket-Accept: {}\r\n\r\n",
+                core::str::from_utf8(&accept).unwrap_or_default(),
+            )
+            .unwrap();
+            socket.write_all(response.as_bytes()).await?;
+            websocket(socket, link).await
+        }
+        ("GET", "/", _) => {
+            let mut response: String<128> = String::new();
+            write!(
+                &mut response,
+                "HTTP/1.1 200 OK\r\nContent-Type: text/html\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+                INDEX.len(),
+            )
+            .unwrap();
+            socket.write_all(response.as_bytes()).await?;
+            socket.write_all(INDEX.as_bytes()).await?;
+            Ok(())
+        }
+        _ => {
+            socket
+                .write_all(b"HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\nConnection: close\r\n\r\n")
+                .await?;
+            Ok(())
+        }
+    }
+}
+
+async fn websocket<T: Transport>(socket: &mut TcpSocket<'_>, link: &mut T) -> Result<(), ServerError> {
+    let mut buf = [0u8; BUFFER_LEN];
+    let mut out = [0u8; BUFFER_LEN];
+    let mut len = 0;
+    loop {
+        if len == buf.len() {
+            return Err(websocket::WebSocketError::TooLong.into());
+        }
+        match select(socket.read(&mut buf[len..]), link.receive()).await {
+            Either::First(read) => {
+                let read = read?;
+                if read == 0 {
+                    return Ok(());
+                }
+                len += read;
+            }
+            Either::Second(Ok(frame)) => {
+                let frame_len = encode(Opcode::Text, &frame.data, &mut out)?;
+                socket.write_all(&out[..frame_len]).await?;
+                continue;
+            }
+            Either::Second(Err(err)) => {
+                warn!("web link: {:?}", err);
+                continue;
+            }
+        }
+
+        while let Some(header) = decode(&mut buf[..len])? {
+            match header.opcode {
+                Opcode::Text | Opcode::Binary => {
+                    if let Err(err) = link.send(&WEB_PEER, &buf[header.payload.clone()]).await {
+                        warn!("web command dropped: {:?}", err);
+                    }
+                }
+                Opcode::Ping => {
+                    let frame_len = encode(Opcode::Pong, &buf[header.payload.clone()], &mut out)?;
+                    socket.write_all(&out[..frame_len]).await?;
+                }
+                Opcode::Close => {
+                    let frame_len = encode(Opcode::Close, &[], &mut out)?;
+                    socket.write_all(&out[..frame_len]).await?;
+                    return Ok(());
+                }
+                Opcode::Continuation | Opcode::Pong => (),
+            }
+            buf.copy_within(header.len..len, 0);
+            len -= header.len;
+        }
+    }
+}
@@ -0,0 +1,48 @@
+use core::str;
+
+const HEADER_END: &[u8] = b"\r\n\r\n";
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum HttpError {
+    BadRequest,
+    TooLong,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Request<'a> {
+    pub method: &'a str,
+    pub path: &'a str,
+    pub websocket_key: Option<&'a str>,
+    // request head, the blank line included
+    pub len: usize,
+}
+
+// Parses the request head at the start of `buf`, None while it is incomplete
+pub fn parse_request(buf: &[u8]) -> Result<Option<Request<'_>>, HttpError> {
+    let Some(end) = buf.windows(HEADER_END.len()).position(|w| w == HEADER_END) else {
+        return Ok(None);
+    };
+    let head = str::from_utf8(&buf[..end]).map_err(|_| HttpError::BadRequest)?;
+    let mut lines = head.split("\r\n");
+
+    let mut request_line = lines.next().ok_or(HttpError::BadRequest)?.split(' ');
+    let method = request_line.next().ok_or(HttpError::BadRequest)?;
+    let path = request_line.next().ok_or(HttpError::BadRequest)?;
+
+    let mut websocket_key = None;
+    for line in lines {
+        let Some((name, value)) = line.split_once(':') else {
+            return Err(HttpError::BadRequest);
+        };
+        if name.trim().eq_ignore_ascii_case("sec-websocket-key") {
+            websocket_key = Some(value.trim());
+        }
+    }
+
+    Ok(Some(Request {
+        method,
+        path,
+        websocket_key,
+        len: end + HEADER_END.len(),
+    }))
+}
@@ -0,0 +1,139 @@
+use core::ops::Range;
+
+use base64::{Engine, engine::general_purpose::STANDARD};
+use sha1::{Digest, Sha1};
+
+const GUID: &[u8] = b"258EAFA5-E914-47DA-95CA-C5AB0DC85B11";
+
+pub const ACCEPT_KEY_LEN: usize = 28;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Opcode {
+    Continuation,
+    Text,
+    Binary,
+    Close,
+    Ping,
+    Pong,
+}
+
+impl Opcode {
+    fn from_bits(bits: u8) -> Option<Self> {
+        match bits {
+            0x0 => Some(Opcode::Continuation),
+            0x1 => Some(Opcode::Text),
+            0x2 => Some(Opcode::Binary),
+            0x8 => Some(Opcode::Close),
+            0x9 => Some(Opcode::Ping),
+            0xa => Some(Opcode::Pong),
+            _ => None,
+        }
+    }
+
+    fn bits(self) -> u8 {
+        match self {
+            Opcode::Continuation => 0x0,
+            Opcode::Text => 0x1,
+            Opcode::Binary => 0x2,
+            Opcode::Close => 0x8,
+            Opcode::Ping => 0x9,
+            Opcode::Pong => 0xa,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum WebSocketError {
+    // clients must mask their frames
+    Unmasked,
+    UnknownOpcode,
+    TooLong,
+    BufferTooSmall,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Header {
+    pub fin: bool,
+    pub opcode: Opcode,
+    pub payload: Range<usize>,
+    // whole frame, header included
+    pub len: usize,
+}
+
+// Sec-WebSocket-Accept for the client's Sec-WebSocket-Key
+pub fn accept_key(key: &[u8]) -> [u8; ACCEPT_KEY_LEN] {
+    let mut sha1 = Sha1::new();
+    sha1.update(key);
+    sha1.update(GUID);
+    let mut accept = [0u8; ACCEPT_KEY_LEN];
+    // 20 bytes of digest always fit into 28 base64 characters
+    let _ = STANDARD.encode_slice(sha1.finalize(), &mut accept);
+    accept
+}
+
+// Decodes a client frame at the start of `buf` and unmasks its payload in place,
+// None while the frame is incomplete
+pub fn decode(buf: &mut [u8]) -> Result<Option<Header>, WebSocketError> {
+    if buf.len() < 2 {
+        return Ok(None);
+    }
+    let fin = buf[0] & 0x80 != 0;
+    let opcode = Opcode::from_bits(buf[0] & 0x0f).ok_or(WebSocketError::UnknownOpcode)?;
+    if buf[1] & 0x80 == 0 {
+        return Err(WebSocketError::Unmasked);
+    }
+
+    let (payload_len, mut offset) = match buf[1] & 0x7f {
+        126 => {
+            if buf.len() < 4 {
+                return Ok(None);
+            }
+            (u16::from_be_bytes([buf[2], buf[3]]) as usize, 4)
+        }
+        127 => return Err(WebSocketError::TooLong),
+        len => (len as usize, 2),
+    };
+
+    if buf.len() < offset + 4 {
+        return Ok(None);
+    }
+    let mask = [buf[offset], buf[offset + 1], buf[offset + 2], buf[offset + 3]];
+    offset += 4;
+
+    let len = offset + payload_len;
+    if buf.len() < len {
+        return Ok(None);
+    }
+    for (i, byte) in buf[offset..len].iter_mut().enumerate() {
+        *byte ^= mask[i % 4];
+    }
+
+    Ok(Some(Header {
+        fin,
+        opcode,
+        payload: offset..len,
+        len,
+    }))
+}
+
+// Encodes a single unmasked server frame, returns its length
+pub fn encode(opcode: Opcode, payload: &[u8], out: &mut [u8]) -> Result<usize, WebSocketError> {
+    let header_len = if payload.len() < 126 { 2 } else { 4 };
+    let len = header_len + payload.len();
+    if payload.len() > u16::MAX as usize {
+        return Err(WebSocketError::TooLong);
+    }
+    if out.len() < len {
+        return Err(WebSocketError::BufferTooSmall);
+    }
+
+    out[0] = 0x80 | opcode.bits();
+    if header_len == 2 {
+        out[1] = payload.len() as u8;
+    } else {
+        out[1] = 126;
+        out[2..4].copy_from_slice(&(payload.len() as u16).to_be_bytes());
+    }
+    out[header_len..len].copy_from_slice(payload);
+    Ok(len)
+}